@@ -5,6 +5,11 @@ use time::PrimitiveDateTime;
 use tokio::io::AsyncBufReadExt;
 use tokio::process::Command;
 
+use viereck::color::Color;
+use viereck::message::Message;
+use viereck::object::Condition;
+use viereck::stylesheet::Stylesheet;
+use viereck::theme::Theme;
 use viereck::Object as VObject;
 
 type Object = VObject<viereck::style::Style>;
@@ -56,43 +61,48 @@ fn redraw_panel(panel: &Panel) -> anyhow::Result<Vec<Object>> {
 
     objs.push({
         Object::Container {
-            background: Some(piet::Color::BLACK),
+            background: Some(theme_color("background")),
             style: Default::default(),
             children: panel
                 .tags
                 .iter()
-                .map(|tag| {
-                    let (fg, bg) = color_for_tag(tag.0);
-
-                    Object::Container {
-                        background: Some(bg),
-                        style: viereck::style::Style {
-                            padding: Some(stretch::geometry::Rect {
-                                start: stretch::style::Dimension::Points(5.),
-                                end: stretch::style::Dimension::Points(5.),
-                                top: stretch::style::Dimension::Points(3.),
-                                bottom: stretch::style::Dimension::Points(3.),
-                            }),
-                            ..Default::default()
-                        },
-                        children: vec![Object::Text {
-                            font: "Noto Sans Mono".into(),
-                            text: tag.1.clone(),
-                            font_size: 12.,
-                            color: fg,
-                            style: Default::default(),
-                        }],
-                        corner_radius: None,
-                    }
+                .map(|tag| Object::Container {
+                    background: None,
+                    style: Default::default(),
+                    children: vec![Object::Text {
+                        font: None,
+                        text: tag.1.clone(),
+                        font_size: None,
+                        overflow: Default::default(),
+                        marquee: None,
+                        attributes: Default::default(),
+                        color: None,
+                        style: Default::default(),
+                    }],
+                    corner_radius: None,
+                    font: None,
+                    font_size: None,
+                    color: None,
+                    slot: None,
+                    attributes: viereck::object::Attributes {
+                        class: vec!["tag".into(), class_for_tag(tag.0).into()],
+                        animation: animation_for_tag(tag.0),
+                        ..Default::default()
+                    },
                 })
                 .collect(),
             corner_radius: None,
+            font: None,
+            font_size: None,
+            color: None,
+            slot: None,
+            attributes: Default::default(),
         }
     });
 
     objs.push({
         Object::Container {
-            background: Some(piet::Color::BLACK),
+            background: Some(theme_color("background")),
             style: viereck::style::Style {
                 flex_grow: Some(1.),
                 padding: Some(stretch::geometry::Rect {
@@ -104,22 +114,30 @@ fn redraw_panel(panel: &Panel) -> anyhow::Result<Vec<Object>> {
                 ..Default::default()
             },
             children: vec![Object::Text {
-                font: "Noto Sans Mono".into(),
+                font: None,
                 text: panel.title.clone(),
-                font_size: 12.,
-                color: piet::Color::WHITE,
+                font_size: None,
+                overflow: Default::default(),
+                marquee: None,
+                attributes: Default::default(),
+                color: None,
                 style: viereck::style::Style {
                     align_content: Some(stretch::style::AlignContent::Center),
                     ..Default::default()
                 },
             }],
             corner_radius: None,
+            font: None,
+            font_size: None,
+            color: None,
+            slot: None,
+            attributes: Default::default(),
         }
     });
 
     objs.push({
         Object::Container {
-            background: Some(piet::Color::BLACK),
+            background: Some(theme_color("background")),
             style: viereck::style::Style {
                 padding: Some(stretch::geometry::Rect {
                     start: stretch::style::Dimension::Points(1.),
@@ -138,16 +156,11 @@ fn redraw_panel(panel: &Panel) -> anyhow::Result<Vec<Object>> {
                         .get::<battery::units::ratio::percent>()
                         as u8;
 
-                    let bg = match pct {
-                        21..=50 => piet::Color::rgb8(0xff, 0xb8, 0x61),
-                        0..=20 => piet::Color::rgb8(0xff, 0x69, 0x61),
-                        _ => piet::Color::WHITE,
-                    };
-
+                    let value = pct as f64;
                     let pct = pct as f32 / 100.;
 
                     childs.push(Object::Container {
-                        background: Some(piet::Color::grey8(0x55)),
+                        background: Some(piet::Color::grey8(0x55).into()),
                         style: viereck::style::Style {
                             align_self: Some(stretch::style::AlignSelf::Stretch),
                             padding: Some(stretch::geometry::Rect {
@@ -159,7 +172,7 @@ fn redraw_panel(panel: &Panel) -> anyhow::Result<Vec<Object>> {
                             ..Default::default()
                         },
                         children: vec![Object::Container {
-                            background: Some(bg),
+                            background: Some(piet::Color::WHITE.into()),
                             style: viereck::style::Style {
                                 align_self: Some(stretch::style::AlignSelf::FlexEnd),
                                 size: Some(stretch::geometry::Size {
@@ -170,16 +183,33 @@ fn redraw_panel(panel: &Panel) -> anyhow::Result<Vec<Object>> {
                             },
                             children: vec![],
                             corner_radius: Some(1.),
+                            font: None,
+                            font_size: None,
+                            color: None,
+                            slot: None,
+                            attributes: viereck::object::Attributes {
+                                value: Some(value),
+                                when: battery_conditions(),
+                                ..Default::default()
+                            },
                         }],
                         corner_radius: None,
+                        font: None,
+                        font_size: None,
+                        color: None,
+                        slot: None,
+                        attributes: Default::default(),
                     })
                 }
 
                 childs.push(Object::Text {
-                    font: "Noto Sans Mono".into(),
+                    font: None,
                     text: panel.time.format("%F %T"),
-                    font_size: 12.,
-                    color: piet::Color::WHITE,
+                    font_size: None,
+                    overflow: Default::default(),
+                    marquee: None,
+                    attributes: Default::default(),
+                    color: None,
                     style: viereck::style::Style {
                         margin: Some(stretch::geometry::Rect {
                             start: stretch::style::Dimension::Points(5.),
@@ -195,6 +225,11 @@ fn redraw_panel(panel: &Panel) -> anyhow::Result<Vec<Object>> {
                 childs
             },
             corner_radius: None,
+            font: None,
+            font_size: None,
+            color: None,
+            slot: None,
+            attributes: Default::default(),
         }
     });
 
@@ -224,28 +259,102 @@ async fn get_herbstclient_tags() -> anyhow::Result<Vec<(Tag, String)>> {
         .collect())
 }
 
-fn color_for_tag(tag: Tag) -> (piet::Color, piet::Color) {
+/// Red up to 20%, orange up to 50%, the background set on the bar otherwise
+fn battery_conditions() -> Vec<Condition> {
+    vec![
+        Condition {
+            max: Some(21.),
+            background: Some(piet::Color::rgb8(0xff, 0x69, 0x61).into()),
+            ..Default::default()
+        },
+        Condition {
+            max: Some(51.),
+            background: Some(piet::Color::rgb8(0xff, 0xb8, 0x61).into()),
+            ..Default::default()
+        },
+    ]
+}
+
+fn theme_color(name: &str) -> Color {
+    Color::Var(name.to_string())
+}
+
+/// The colors of the panel, sent once at the start
+fn panel_theme() -> Theme {
+    let mut theme = Theme::default();
+    theme.insert("background", piet::Color::BLACK);
+    theme.insert("foreground", piet::Color::WHITE);
+    theme.insert("inactive", piet::Color::rgb8(0xDD, 0xDD, 0xDD));
+    theme.insert("muted", piet::Color::rgb8(0x77, 0x77, 0x77));
+    theme.insert("accent", piet::Color::rgb8(0x9F, 0xBC, 0x00));
+    theme.insert("accent-text", piet::Color::rgb8(0x10, 0x10, 0x10));
+    theme.insert("urgent", piet::Color::rgb8(0xFF, 0x00, 0x00));
+    theme.insert("urgent-dim", piet::Color::rgb8(0x55, 0x00, 0x00));
+    theme
+}
+
+/// How the tags and texts of the panel look, sent once at the start
+fn panel_stylesheet() -> anyhow::Result<Stylesheet> {
+    Ok(serde_json::from_value(serde_json::json!([
+        {
+            "selector": ".tag",
+            "background": "$background",
+            "style": {
+                "padding": {
+                    "start": { "points": 5 },
+                    "end": { "points": 5 },
+                    "top": { "points": 3 },
+                    "bottom": { "points": 3 }
+                }
+            }
+        },
+        {
+            "selector": "Text",
+            "font": "Noto Sans Mono",
+            "font_size": 12,
+            "color": "$foreground"
+        },
+        { "selector": ".tag Text", "color": "$inactive" },
+        { "selector": ".focused", "background": "$accent" },
+        { "selector": ".focused Text", "color": "$accent-text" },
+        { "selector": ".not-empty", "background": "$muted" },
+        { "selector": ".urgent", "background": "$urgent" },
+        {
+            "selector": ".not-focused Text, .not-empty Text, .urgent Text",
+            "color": "$foreground"
+        }
+    ]))?)
+}
+
+fn class_for_tag(tag: Tag) -> &'static str {
+    match tag {
+        Tag::Focused => "focused",
+        Tag::NotFocused => "not-focused",
+        Tag::NotEmpty => "not-empty",
+        Tag::Alterting => "urgent",
+        _ => "empty",
+    }
+}
+
+/// Urgent tags blink between their color and a darker one
+fn animation_for_tag(tag: Tag) -> Option<viereck::animation::Animation> {
     match tag {
-        Tag::Focused => (
-            piet::Color::rgb8(0x10, 0x10, 0x10),
-            piet::Color::rgb8(0x9F, 0xBC, 0x00),
-        ),
-        Tag::NotFocused => (
-            piet::Color::rgb8(0xFF, 0xFF, 0xFF),
-            piet::Color::rgb8(0x00, 0x00, 0x00),
-        ),
-        Tag::NotEmpty => (
-            piet::Color::rgb8(0xFF, 0xFF, 0xFF),
-            piet::Color::rgb8(0x77, 0x77, 0x77),
-        ),
-        Tag::Alterting => (
-            piet::Color::rgb8(0xFF, 0xFF, 0xFF),
-            piet::Color::rgb8(0xFF, 0x00, 0x00),
-        ),
-        _ => (
-            piet::Color::rgb8(0xDD, 0xDD, 0xDD),
-            piet::Color::rgb8(0x00, 0x00, 0x00),
-        ),
+        Tag::Alterting => Some(viereck::animation::Animation {
+            keyframes: vec![viereck::animation::Keyframe {
+                offset: 1.,
+                opacity: None,
+                background: Some(theme_color("urgent-dim")),
+                color: None,
+                translate: None,
+                scale: None,
+            }],
+            duration: 500,
+            delay: 0,
+            easing: viereck::animation::Easing::EaseInOut,
+            iterations: viereck::animation::IterationCount::Infinite,
+            direction: viereck::animation::Direction::Alternate,
+        }),
+        _ => None,
     }
 }
 
@@ -300,6 +409,17 @@ async fn main() -> anyhow::Result<()> {
         time: PrimitiveDateTime::now(),
     };
 
+    println!(
+        "{}",
+        serde_json::to_string(&Message::<viereck::style::Style>::Theme(panel_theme()))?
+    );
+    println!(
+        "{}",
+        serde_json::to_string(&Message::<viereck::style::Style>::Stylesheet(
+            panel_stylesheet()?
+        ))?
+    );
+
     while let Some(ev) = events.next().await {
         update_panel(&mut panel, ev).await?;
         let p = redraw_panel(&panel)?;
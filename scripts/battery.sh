@@ -4,11 +4,6 @@ set -e
 set -o nounset
 set -o pipefail
 
-container() { ../target/release/viereck-container "$@" ;}
-text() { ../target/release/viereck-text "$@" ;}
-
-simple_text() { text --font "DejaVu Sans Mono" --font-size 12 "$@" ;}
-
 uniq_linebuffered() {
   awk '$0 != l { print ; l=$0 ; fflush(); }' "$@"
 }
@@ -21,22 +16,11 @@ debug() {
   ELAPSED=$(date +%s.%N)
 }
 
-BAT_PCT=$(acpi | cut -f2 -d, | tr -d \[:space:\])
-
-BG="0x00FF00FF"
-
-if [ "${BAT_PCT::-1}" -lt 40 ]; then
-  BG="0xFFFF00FF"
-elif [ "${BAT_PCT::-1}" -lt 20 ]; then
-  BG="0xFF0000FF"
-fi
-
-#BAT_PCT="60%"
-
-BAT_TEXT=$(simple_text --text "$BAT_PCT" --color 0xFF)
+# Registered once, every update after it only sends the data
+TEMPLATE='{"template": {"name": "battery", "objects": [{"type": "Container", "style": {"padding": {"start": {"points": 5}, "end": {"points": 5}, "top": {"points": 5}, "bottom": {"points": 5}}, "flexGrow": 1}, "font": "DejaVu Sans Mono", "font_size": 12, "color": "black", "children": [{"type": "Container", "background": "#00ff00", "value": "{{pct}}", "when": [{"max": 20, "background": "#ff0000"}, {"max": 40, "background": "#ffff00"}], "style": {"alignItems": "center", "justifyContent": "center", "size": {"width": {"percent": "{{ratio}}"}, "height": "auto"}}, "children": [{"type": "Text", "text": "{{pct}}%", "style": {}}]}]}]}}'
 
-INNER=$(container --width "$BAT_PCT" --background "$BG" --align-items center --justify-content center -c "$BAT_TEXT")
+echo "$TEMPLATE"
 
-OUTER=$(container --padding 5 -c "$INNER" --grow 1)
+BAT_PCT=$(acpi | cut -f2 -d, | tr -d \[:space:]%)
 
-echo "[$OUTER]"
+echo "{\"render\": \"battery\", \"data\": {\"pct\": $BAT_PCT, \"ratio\": $(awk "BEGIN { print $BAT_PCT / 100 }")}}"
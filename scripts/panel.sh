@@ -66,9 +66,7 @@ debug() {
           "font": "DejaVu Sans Mono",
           "text": "${i:1}",
           "font_size": 12,
-          "color": {
-            "Rgba32": $((16#$text_color))
-          },
+          "color": "#$text_color",
           "style": {}
         }
 EOF
@@ -92,11 +90,9 @@ EOF
                 "points": 5
               }
             },
-            "align_items": "center"
+            "alignItems": "center"
           },
-          "background": {
-            "Rgba32": $((16#$bg_color))
-          } 
+          "background": "#$bg_color"
         }
 EOF
 )
@@ -0,0 +1,529 @@
+use schemars::JsonSchema;
+use serde::de::Error as _;
+use serde::{Deserializer, Serializer};
+use serde_derive::{Deserialize, Serialize};
+use std::time::Duration;
+
+use crate::color::Color;
+
+/// How often animated properties are updated
+pub const FRAME_INTERVAL: Duration = Duration::from_millis(16);
+
+/// The timing function of an animation, as in CSS
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum Easing {
+    Linear,
+    Ease,
+    EaseIn,
+    EaseOut,
+    EaseInOut,
+}
+
+impl Default for Easing {
+    fn default() -> Easing {
+        Easing::Ease
+    }
+}
+
+impl Easing {
+    /// Map the progress `t` (from 0.0 to 1.0) of an animation
+    pub fn apply(self, t: f64) -> f64 {
+        let t = t.max(0.).min(1.);
+        match self {
+            Easing::Linear => t,
+            Easing::Ease => cubic_bezier(0.25, 0.1, 0.25, 1.0, t),
+            Easing::EaseIn => cubic_bezier(0.42, 0.0, 1.0, 1.0, t),
+            Easing::EaseOut => cubic_bezier(0.0, 0.0, 0.58, 1.0, t),
+            Easing::EaseInOut => cubic_bezier(0.42, 0.0, 0.58, 1.0, t),
+        }
+    }
+}
+
+/// Evaluate the CSS `cubic-bezier(x1, y1, x2, y2)` timing function at `x`
+fn cubic_bezier(x1: f64, y1: f64, x2: f64, y2: f64, x: f64) -> f64 {
+    let bezier = |a: f64, b: f64, t: f64| {
+        let u = 1. - t;
+        3. * u * u * t * a + 3. * u * t * t * b + t * t * t
+    };
+
+    // Find the `t` giving `x` by bisection, the curve is monotonic in x
+    let (mut low, mut high) = (0., 1.);
+    let mut t = x;
+    for _ in 0..32 {
+        let current = bezier(x1, x2, t);
+        if (current - x).abs() < 1e-6 {
+            break;
+        }
+        if current < x {
+            low = t;
+        } else {
+            high = t;
+        }
+        t = (low + high) / 2.;
+    }
+
+    bezier(y1, y2, t)
+}
+
+pub fn lerp(from: f64, to: f64, t: f64) -> f64 {
+    from + (to - from) * t
+}
+
+pub fn lerp_rect(from: &kurbo::Rect, to: &kurbo::Rect, t: f64) -> kurbo::Rect {
+    kurbo::Rect::new(
+        lerp(from.x0, to.x0, t),
+        lerp(from.y0, to.y0, t),
+        lerp(from.x1, to.x1, t),
+        lerp(from.y1, to.y1, t),
+    )
+}
+
+pub fn lerp_color(from: &piet::Color, to: &piet::Color, t: f64) -> piet::Color {
+    let (from, to) = (from.as_rgba_u32(), to.as_rgba_u32());
+    let channel = |shift: u32| {
+        let from = ((from >> shift) & 0xFF) as f64;
+        let to = ((to >> shift) & 0xFF) as f64;
+        (lerp(from, to, t).round() as u32) << shift
+    };
+    piet::Color::from_rgba32_u32(channel(24) | channel(16) | channel(8) | channel(0))
+}
+
+/// Interpolate between optional colors, a missing color is a transparent
+/// version of the other one
+pub fn lerp_opt_color(
+    from: Option<&piet::Color>,
+    to: Option<&piet::Color>,
+    t: f64,
+) -> Option<piet::Color> {
+    let transparent =
+        |color: &piet::Color| piet::Color::from_rgba32_u32(color.as_rgba_u32() & !0xFF);
+    match (from, to) {
+        (Some(from), Some(to)) => Some(lerp_color(from, to, t)),
+        (Some(from), None) => Some(lerp_color(from, &transparent(from), t)),
+        (None, Some(to)) => Some(lerp_color(&transparent(to), to, t)),
+        (None, None) => None,
+    }
+}
+
+/// How often an animation is played
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IterationCount {
+    Count(f64),
+    Infinite,
+}
+
+impl Default for IterationCount {
+    fn default() -> IterationCount {
+        IterationCount::Count(1.)
+    }
+}
+
+impl serde::Serialize for IterationCount {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            IterationCount::Count(count) => serializer.serialize_f64(*count),
+            IterationCount::Infinite => serializer.serialize_str("infinite"),
+        }
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for IterationCount {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Repr {
+            Count(f64),
+            Keyword(String),
+        }
+
+        match <Repr as serde::Deserialize>::deserialize(deserializer)? {
+            Repr::Count(count) => Ok(IterationCount::Count(count)),
+            Repr::Keyword(keyword) if keyword == "infinite" => Ok(IterationCount::Infinite),
+            Repr::Keyword(keyword) => Err(D::Error::custom(format!(
+                "{} needs to be a number or 'infinite'",
+                keyword
+            ))),
+        }
+    }
+}
+
+impl JsonSchema for IterationCount {
+    fn schema_name() -> String {
+        "IterationCount".to_string()
+    }
+
+    fn json_schema(gen: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+        use schemars::schema::{InstanceType, SchemaObject, SubschemaValidation};
+
+        let infinite = SchemaObject {
+            instance_type: Some(InstanceType::String.into()),
+            enum_values: Some(vec!["infinite".into()]),
+            ..Default::default()
+        };
+
+        SchemaObject {
+            subschemas: Some(Box::new(SubschemaValidation {
+                any_of: Some(vec![gen.subschema_for::<f64>(), infinite.into()]),
+                ..Default::default()
+            })),
+            ..Default::default()
+        }
+        .into()
+    }
+}
+
+/// In which direction the iterations of an animation are played, as in CSS
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum Direction {
+    Normal,
+    Reverse,
+    /// Every other iteration is played backwards
+    Alternate,
+    AlternateReverse,
+}
+
+impl Default for Direction {
+    fn default() -> Direction {
+        Direction::Normal
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct Translation {
+    #[serde(default)]
+    pub x: f64,
+    #[serde(default)]
+    pub y: f64,
+}
+
+/// The values of some properties at one point of an animation
+///
+/// Properties that are left out are interpolated between the surrounding
+/// keyframes that set them, or the value of the object itself.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct Keyframe {
+    /// From 0.0 (start) to 1.0 (end) of an iteration
+    pub offset: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub opacity: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub background: Option<Color>,
+    /// The text color or tint of an image
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub color: Option<Color>,
+    /// Moves the object and its children, in points
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub translate: Option<Translation>,
+    /// Scales the object and its children around its center
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scale: Option<f64>,
+}
+
+/// An animation played by the renderer on its own
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct Animation {
+    pub keyframes: Vec<Keyframe>,
+    /// Of one iteration, in milliseconds
+    pub duration: u64,
+    /// Before the first iteration starts, in milliseconds
+    #[serde(default)]
+    pub delay: u64,
+    /// Applied between each pair of keyframes
+    #[serde(default)]
+    pub easing: Easing,
+    #[serde(default)]
+    pub iterations: IterationCount,
+    #[serde(default)]
+    pub direction: Direction,
+}
+
+/// Where an animation is at
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Progress {
+    /// The delay has not passed yet
+    Waiting,
+    /// From 0.0 to 1.0 through the current iteration, taking the direction
+    /// into account
+    Running(f64),
+    Finished,
+}
+
+impl Animation {
+    pub fn progress(&self, elapsed: Duration) -> Progress {
+        let elapsed = match elapsed.checked_sub(Duration::from_millis(self.delay)) {
+            Some(elapsed) => elapsed,
+            None => return Progress::Waiting,
+        };
+
+        let duration = Duration::from_millis(self.duration).as_secs_f64();
+        if duration <= 0. {
+            return Progress::Finished;
+        }
+
+        let iteration = elapsed.as_secs_f64() / duration;
+        if let IterationCount::Count(count) = self.iterations {
+            if iteration >= count {
+                return Progress::Finished;
+            }
+        }
+
+        let index = iteration.floor();
+        let fraction = iteration - index;
+        let reversed = match self.direction {
+            Direction::Normal => false,
+            Direction::Reverse => true,
+            Direction::Alternate => index as u64 % 2 == 1,
+            Direction::AlternateReverse => index as u64 % 2 == 0,
+        };
+
+        Progress::Running(if reversed { 1. - fraction } else { fraction })
+    }
+
+    /// The keyframes around `progress` that set a property, with the eased
+    /// progress between them
+    ///
+    /// A missing keyframe stands for the value of the object itself.
+    fn segment(
+        &self,
+        progress: f64,
+        sets: impl Fn(&Keyframe) -> bool,
+    ) -> (Option<&Keyframe>, Option<&Keyframe>, f64) {
+        let mut keyframes: Vec<&Keyframe> = self.keyframes.iter().filter(|k| sets(k)).collect();
+        keyframes.sort_by(|a, b| {
+            a.offset
+                .partial_cmp(&b.offset)
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+
+        let span = |from: f64, to: f64| {
+            if to > from {
+                self.easing.apply((progress - from) / (to - from))
+            } else {
+                1.
+            }
+        };
+
+        let mut from = (0., None);
+        for keyframe in keyframes {
+            if keyframe.offset >= progress {
+                return (from.1, Some(keyframe), span(from.0, keyframe.offset));
+            }
+            from = (keyframe.offset, Some(keyframe));
+        }
+
+        (from.1, None, span(from.0, 1.))
+    }
+
+    /// The value of a number property at `progress`, `base` being the value of
+    /// the object itself
+    pub fn value(&self, progress: f64, base: f64, get: impl Fn(&Keyframe) -> Option<f64>) -> f64 {
+        let (from, to, t) = self.segment(progress, |keyframe| get(keyframe).is_some());
+        lerp(
+            from.and_then(&get).unwrap_or(base),
+            to.and_then(&get).unwrap_or(base),
+            t,
+        )
+    }
+
+    /// The value of a color property at `progress`, `base` being the color of
+    /// the object itself
+    pub fn color(
+        &self,
+        progress: f64,
+        base: Option<&piet::Color>,
+        get: impl Fn(&Keyframe) -> Option<piet::Color>,
+    ) -> Option<piet::Color> {
+        let (from, to, t) = self.segment(progress, |keyframe| get(keyframe).is_some());
+        let from = from.and_then(&get).or_else(|| base.cloned());
+        let to = to.and_then(&get).or_else(|| base.cloned());
+        lerp_opt_color(from.as_ref(), to.as_ref(), t)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-4,
+            "{} is not {}",
+            actual,
+            expected
+        );
+    }
+
+    fn keyframe(offset: f64, opacity: Option<f64>, scale: Option<f64>) -> Keyframe {
+        Keyframe {
+            offset,
+            opacity,
+            background: None,
+            color: None,
+            translate: None,
+            scale,
+        }
+    }
+
+    fn animation(iterations: IterationCount, direction: Direction) -> Animation {
+        Animation {
+            keyframes: vec![],
+            duration: 1000,
+            delay: 200,
+            easing: Easing::Linear,
+            iterations,
+            direction,
+        }
+    }
+
+    #[test]
+    fn easing_endpoints() {
+        let easings = [
+            Easing::Linear,
+            Easing::Ease,
+            Easing::EaseIn,
+            Easing::EaseOut,
+            Easing::EaseInOut,
+        ];
+        for easing in easings.iter() {
+            assert_close(easing.apply(0.), 0.);
+            assert_close(easing.apply(1.), 1.);
+            assert_close(easing.apply(-1.), 0.);
+            assert_close(easing.apply(2.), 1.);
+        }
+    }
+
+    #[test]
+    fn cubic_bezier_curves() {
+        let table = [
+            (Easing::Linear, 0.3, 0.3),
+            (Easing::Ease, 0.5, 0.8024),
+            (Easing::EaseIn, 0.5, 0.3154),
+            (Easing::EaseOut, 0.5, 0.6846),
+            (Easing::EaseInOut, 0.5, 0.5),
+            (Easing::EaseInOut, 0.25, 0.1292),
+        ];
+        for (easing, t, expected) in table.iter() {
+            assert_close(easing.apply(*t), *expected);
+        }
+
+        // A straight line as a curve
+        for x in &[0., 0.1, 0.5, 0.9, 1.] {
+            assert_close(cubic_bezier(1. / 3., 1. / 3., 2. / 3., 2. / 3., *x), *x);
+        }
+    }
+
+    #[test]
+    fn lerp_helpers() {
+        assert_close(lerp(2., 4., 0.), 2.);
+        assert_close(lerp(2., 4., 0.5), 3.);
+        assert_close(lerp(2., 4., 1.), 4.);
+        assert_eq!(
+            lerp_rect(
+                &kurbo::Rect::new(0., 0., 10., 10.),
+                &kurbo::Rect::new(10., 20., 30., 40.),
+                0.5
+            ),
+            kurbo::Rect::new(5., 10., 20., 25.)
+        );
+
+        let black = piet::Color::from_rgba32_u32(0x0000_00ff);
+        let white = piet::Color::from_rgba32_u32(0xffff_ffff);
+        assert_eq!(lerp_color(&black, &white, 0.).as_rgba_u32(), 0x0000_00ff);
+        assert_eq!(lerp_color(&black, &white, 0.5).as_rgba_u32(), 0x8080_80ff);
+        assert_eq!(lerp_color(&black, &white, 1.).as_rgba_u32(), 0xffff_ffff);
+
+        // A missing color fades the other one in or out
+        let red = piet::Color::from_rgba32_u32(0xff00_00ff);
+        let faded = |from, to| lerp_opt_color(from, to, 0.5).map(|color| color.as_rgba_u32());
+        assert_eq!(faded(Some(&red), None), Some(0xff00_0080));
+        assert_eq!(faded(None, Some(&red)), Some(0xff00_0080));
+        assert_eq!(faded(None, None), None);
+    }
+
+    #[test]
+    fn progress() {
+        use IterationCount::{Count, Infinite};
+
+        let table = [
+            (Count(1.), Direction::Normal, 100, None),
+            (Count(1.), Direction::Normal, 200, Some(0.)),
+            (Count(1.), Direction::Normal, 700, Some(0.5)),
+            (Count(1.), Direction::Normal, 1199, Some(0.999)),
+            (Count(1.), Direction::Reverse, 450, Some(0.75)),
+            (Count(3.), Direction::Alternate, 450, Some(0.25)),
+            (Count(3.), Direction::Alternate, 1450, Some(0.75)),
+            (Count(3.), Direction::Alternate, 2450, Some(0.25)),
+            (Count(3.), Direction::AlternateReverse, 450, Some(0.75)),
+            (Count(3.), Direction::AlternateReverse, 1450, Some(0.25)),
+            (Count(2.5), Direction::Normal, 2600, Some(0.4)),
+            (Infinite, Direction::Normal, 10_450, Some(0.25)),
+            (Infinite, Direction::Alternate, 11_450, Some(0.75)),
+        ];
+        for (iterations, direction, elapsed, expected) in table.iter() {
+            let progress =
+                animation(*iterations, *direction).progress(Duration::from_millis(*elapsed));
+            match (progress, expected) {
+                (Progress::Running(progress), Some(expected)) => assert_close(progress, *expected),
+                (Progress::Waiting, None) => {}
+                _ => panic!("{:?} after {}ms with {:?}", progress, elapsed, iterations),
+            }
+        }
+    }
+
+    #[test]
+    fn finite_iterations_finish() {
+        let table = [
+            (IterationCount::Count(1.), 1200),
+            (IterationCount::Count(1.), 5000),
+            (IterationCount::Count(2.5), 2700),
+            (IterationCount::Count(0.), 200),
+        ];
+        for (iterations, elapsed) in table.iter() {
+            let animation = animation(*iterations, Direction::Alternate);
+            assert_eq!(
+                animation.progress(Duration::from_millis(*elapsed)),
+                Progress::Finished
+            );
+        }
+
+        let mut animation = animation(IterationCount::Infinite, Direction::Normal);
+        animation.duration = 0;
+        assert_eq!(
+            animation.progress(Duration::from_millis(500)),
+            Progress::Finished
+        );
+    }
+
+    #[test]
+    fn keyframe_segments() {
+        let mut animation = animation(IterationCount::Count(1.), Direction::Normal);
+        animation.keyframes = vec![
+            keyframe(1., Some(1.), None),
+            keyframe(0.5, None, Some(2.)),
+            keyframe(0., Some(0.), None),
+        ];
+
+        // Keyframes that don't set a property are skipped
+        let opacity = |progress| animation.value(progress, 0.3, |k| k.opacity);
+        assert_close(opacity(0.), 0.);
+        assert_close(opacity(0.5), 0.5);
+        assert_close(opacity(1.), 1.);
+
+        // The object itself stands in at the start and end
+        let scale = |progress| animation.value(progress, 1., |k| k.scale);
+        assert_close(scale(0.), 1.);
+        assert_close(scale(0.25), 1.5);
+        assert_close(scale(0.5), 2.);
+        assert_close(scale(0.75), 1.5);
+        assert_close(scale(1.), 1.);
+
+        // Easing applies to each segment on its own
+        animation.easing = Easing::EaseIn;
+        let (from, to, t) = animation.segment(0.75, |k| k.scale.is_some());
+        assert_eq!(from.map(|k| k.offset), Some(0.5));
+        assert!(to.is_none());
+        assert_close(t, Easing::EaseIn.apply(0.5));
+    }
+}
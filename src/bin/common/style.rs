@@ -1,7 +1,8 @@
 use anyhow::{anyhow, Result};
-use serde_derive::Serialize;
 use structopt::StructOpt;
 
+use viereck::style::Style;
+
 fn parse_align_items(input: &str) -> Result<stretch::style::AlignItems> {
     Ok(match input {
         "flex_start" => stretch::style::AlignItems::FlexStart,
@@ -197,50 +198,3 @@ impl StyleOpts {
         }
     }
 }
-
-#[derive(Debug, Serialize, Default, Clone)]
-#[serde(rename_all = "camelCase")]
-pub struct Style {
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub display: Option<stretch::style::Display>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub position_type: Option<stretch::style::PositionType>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub direction: Option<stretch::style::Direction>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub flex_direction: Option<stretch::style::FlexDirection>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub flex_wrap: Option<stretch::style::FlexWrap>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub overflow: Option<stretch::style::Overflow>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub align_items: Option<stretch::style::AlignItems>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub align_self: Option<stretch::style::AlignSelf>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub align_content: Option<stretch::style::AlignContent>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub justify_content: Option<stretch::style::JustifyContent>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub position: Option<stretch::geometry::Rect<stretch::style::Dimension>>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub margin: Option<stretch::geometry::Rect<stretch::style::Dimension>>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub padding: Option<stretch::geometry::Rect<stretch::style::Dimension>>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub border: Option<stretch::geometry::Rect<stretch::style::Dimension>>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub flex_grow: Option<f32>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub flex_shrink: Option<f32>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub flex_basis: Option<stretch::style::Dimension>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub size: Option<stretch::geometry::Size<stretch::style::Dimension>>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub min_size: Option<stretch::geometry::Size<stretch::style::Dimension>>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub max_size: Option<stretch::geometry::Size<stretch::style::Dimension>>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub aspect_ratio: Option<stretch::number::Number>,
-}
@@ -4,11 +4,8 @@ use serde_json::to_string;
 use structopt::StructOpt;
 
 mod common;
-use common::style::Style;
-
-fn parse_hex(input: &str) -> Result<u32> {
-    Ok(u32::from_str_radix(input.trim_start_matches("0x"), 16)?)
-}
+use viereck::color::Color;
+use viereck::style::Style;
 
 #[derive(Debug, StructOpt)]
 #[structopt(
@@ -23,37 +20,52 @@ struct CmdOptions {
     children: Vec<serde_json::Value>,
     /// Background
     ///
-    /// In rgba hex format 0xXXXXXXXX
-    #[structopt(short, long, parse(try_from_str = parse_hex))]
-    background: Option<u32>,
+    /// As '#rrggbb', '#rrggbbaa', a named color, 'rgb(...)', 'hsl(...)' or
+    /// '$name' from the theme
+    #[structopt(short, long)]
+    background: Option<Color>,
+    /// Font inherited by the texts inside
+    #[structopt(short, long)]
+    font: Option<String>,
+    /// Font size inherited by the texts inside
+    #[structopt(short = "-z", long)]
+    font_size: Option<f64>,
+    /// Text color inherited by the texts inside
+    ///
+    /// As '#rrggbb', '#rrggbbaa', a named color, 'rgb(...)', 'hsl(...)' or
+    /// '$name' from the theme
+    #[structopt(long)]
+    color: Option<Color>,
+    /// Make the container a slot of this name, whose children can be sent on
+    /// their own
+    #[structopt(long)]
+    slot: Option<String>,
+    /// Class to select the container by in stylesheets, can be given multiple
+    /// times
+    #[structopt(long)]
+    class: Vec<String>,
 }
 
 //
 // KEEP THE BELOW ENUM IN SYNC!!
 //
-mod opt_external_color {
-    use serde::{Serialize, Serializer};
-    use viereck::object::ColorDef;
-
-    pub fn serialize<S>(value: &Option<piet::Color>, serializer: S) -> Result<S::Ok, S::Error>
-    where
-        S: Serializer,
-    {
-        #[derive(Serialize)]
-        struct Helper<'a>(#[serde(with = "ColorDef")] &'a piet::Color);
-
-        value.as_ref().map(Helper).serialize(serializer)
-    }
-}
-
 #[derive(Debug, Clone, Serialize)]
 #[serde(tag = "type")]
 enum Object {
     Container {
         children: Vec<serde_json::Value>,
         style: Style,
-        #[serde(default, with = "opt_external_color")]
-        background: Option<piet::Color>,
+        background: Option<Color>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        font: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        font_size: Option<f64>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        color: Option<Color>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        slot: Option<String>,
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        class: Vec<String>,
     },
 }
 
@@ -62,8 +74,13 @@ fn main() -> Result<()> {
 
     let obj = Object::Container {
         style: opt.style.to_style(),
-        background: opt.background.map(piet::Color::from_rgba32_u32),
+        background: opt.background,
         children: opt.children,
+        font: opt.font,
+        font_size: opt.font_size,
+        color: opt.color,
+        slot: opt.slot,
+        class: opt.class,
     };
 
     println!("{}", to_string(&obj)?);
@@ -1,14 +1,54 @@
-use anyhow::Result;
-use image::GenericImageView;
+use anyhow::{anyhow, Result};
 use serde_json::to_string;
 use stretch::style::Dimension;
 use structopt::StructOpt;
 
 mod common;
 
-use common::style::Style;
+use viereck::color::Color;
+use viereck::object::{Attributes, Interpolation, Object, ObjectFit, ObjectPosition};
+use viereck::picture::Picture;
+use viereck::style::Style;
 
-use viereck::object::Object;
+fn parse_object_fit(input: &str) -> Result<ObjectFit> {
+    Ok(match input {
+        "fill" => ObjectFit::Fill,
+        "contain" => ObjectFit::Contain,
+        "cover" => ObjectFit::Cover,
+        "none" => ObjectFit::None,
+        "scale-down" => ObjectFit::ScaleDown,
+        _ => {
+            return Err(anyhow!(
+                "{} needs to be one of: 'fill', 'contain', 'cover', 'none', 'scale-down'",
+                input
+            ))
+        }
+    })
+}
+
+fn parse_object_position(input: &str) -> Result<ObjectPosition> {
+    let mut parts = input.split(',').map(|p| p.trim().parse::<f64>());
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some(Ok(x)), Some(Ok(y)), None) => Ok(ObjectPosition { x, y }),
+        _ => Err(anyhow!(
+            "{} is not a position, expected 'x,y' with both between 0.0 and 1.0",
+            input
+        )),
+    }
+}
+
+fn parse_interpolation(input: &str) -> Result<Interpolation> {
+    Ok(match input {
+        "nearest" => Interpolation::Nearest,
+        "bilinear" => Interpolation::Bilinear,
+        _ => {
+            return Err(anyhow!(
+                "{} needs to be one of: 'nearest', 'bilinear'",
+                input
+            ))
+        }
+    })
+}
 
 #[derive(Debug, StructOpt)]
 #[structopt(
@@ -18,9 +58,19 @@ use viereck::object::Object;
 struct CmdOptions {
     #[structopt(flatten)]
     style: common::style::StyleOpts,
-    /// Path to PNG
-    #[structopt(short, long)]
-    path: String,
+    /// Path to the image, any common raster format or SVG
+    #[structopt(short, long, required_unless = "icon")]
+    path: Option<String>,
+    /// Name of an icon to look up in the icon theme instead of a path
+    #[structopt(short, long, conflicts_with = "path")]
+    icon: Option<String>,
+    /// Size of the icon to look up
+    #[structopt(long, default_value = "16")]
+    icon_size: u32,
+    /// Icon theme to look the icon up in, defaults to the one configured for
+    /// GTK and falls back to 'hicolor'
+    #[structopt(long)]
+    icon_theme: Option<String>,
     /// Do not read the size of the image
     ///
     /// If this is not set, the file is accessed and its size read
@@ -32,23 +82,52 @@ struct CmdOptions {
     /// width are set!
     #[structopt(short, long)]
     allow_deform: bool,
+    /// How to fit the image into its box
+    ///
+    /// One of 'fill', 'contain', 'cover', 'none', 'scale-down'. If set, the
+    /// aspect ratio is not fixed as the image is fitted while drawing.
+    #[structopt(long, parse(try_from_str = parse_object_fit))]
+    object_fit: Option<ObjectFit>,
+    /// Where to align the image inside its box
+    ///
+    /// In the format 'x,y', going from 0.0 (left/top) to 1.0 (right/bottom)
+    #[structopt(long, parse(try_from_str = parse_object_position))]
+    object_position: Option<ObjectPosition>,
+    /// How to scale the image, 'nearest' or 'bilinear'
+    #[structopt(long, parse(try_from_str = parse_interpolation))]
+    interpolation: Option<Interpolation>,
+    /// Recolor the image, using it only as a mask
+    ///
+    /// As '#rrggbb', '#rrggbbaa', a named color, 'rgb(...)', 'hsl(...)' or
+    /// '$name' from the theme
+    #[structopt(long)]
+    tint: Option<Color>,
+    /// Class to select the image by in stylesheets, can be given multiple times
+    #[structopt(long)]
+    class: Vec<String>,
 }
 
 fn main() -> Result<()> {
     let mut opt = CmdOptions::from_args();
 
-    let file = std::fs::File::open(&opt.path)?;
-
-    let img = image::load(std::io::BufReader::new(file), image::ImageFormat::PNG)?;
-
-    let (width, height) = img.dimensions();
-
     if !opt.no_size_read {
+        let (width, height) = match &opt.path {
+            // Icons are square, the size of the found file might only be the closest match
+            None => (opt.icon_size, opt.icon_size),
+            Some(path) if path.ends_with(".svg") || path.ends_with(".svgz") => {
+                let (width, height) = Picture::from_path(path)?.size();
+                (width as u32, height as u32)
+            }
+            Some(path) => image::io::Reader::open(path)?
+                .with_guessed_format()?
+                .into_dimensions()?,
+        };
+
         opt.style.height = Some(Dimension::Points(height as f32));
         opt.style.width = Some(Dimension::Points(width as f32));
     }
 
-    if !opt.allow_deform {
+    if !opt.allow_deform && opt.object_fit.is_none() {
         opt.style.aspect_ratio = match (opt.style.width, opt.style.height) {
             (
                 Some(stretch::style::Dimension::Points(w)),
@@ -60,7 +139,20 @@ fn main() -> Result<()> {
 
     let obj = Object::<Style>::Image {
         style: opt.style.to_style(),
+        icon_size: opt.icon.as_ref().map(|_| opt.icon_size),
         path: opt.path,
+        svg: None,
+        data: None,
+        icon: opt.icon,
+        icon_theme: opt.icon_theme,
+        object_fit: opt.object_fit.unwrap_or_default(),
+        object_position: opt.object_position.unwrap_or_default(),
+        interpolation: opt.interpolation.unwrap_or_default(),
+        tint: opt.tint,
+        attributes: Attributes {
+            class: opt.class,
+            ..Default::default()
+        },
     };
 
     println!("{}", to_string(&obj)?);
@@ -0,0 +1,34 @@
+use anyhow::{Context, Result};
+use std::io::Write;
+use std::os::unix::net::UnixStream;
+use structopt::StructOpt;
+
+#[derive(Debug, StructOpt)]
+#[structopt(
+    name = "viereck-msg",
+    about = "viereck-msg sends messages to a viereck started with --listen"
+)]
+struct CmdOptions {
+    /// The socket viereck listens on
+    #[structopt(short, long, env = "VIERECK_SOCKET")]
+    socket: String,
+    /// The message to send, e.g. the output of viereck-text, read from stdin
+    /// if left out
+    message: Option<String>,
+}
+
+fn main() -> Result<()> {
+    let opt = CmdOptions::from_args();
+
+    let mut socket = UnixStream::connect(&opt.socket)
+        .with_context(|| format!("Could not connect to {}", opt.socket))?;
+
+    match opt.message {
+        Some(message) => writeln!(socket, "{}", message)?,
+        None => {
+            std::io::copy(&mut std::io::stdin(), &mut socket)?;
+        }
+    }
+
+    Ok(())
+}
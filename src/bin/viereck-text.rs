@@ -4,9 +4,9 @@ use structopt::StructOpt;
 
 mod common;
 
-use common::style::Style;
-
-use viereck::object::Object;
+use viereck::color::Color;
+use viereck::object::{Attributes, Marquee, MarqueeMode, Object, Overflow};
+use viereck::style::Style;
 
 pub fn parse_dimension(input: &str) -> Result<stretch::style::Dimension> {
     if let "auto" = input {
@@ -30,8 +30,26 @@ pub fn parse_object(input: &str) -> Result<Object> {
     Ok(from_str(input)?)
 }
 
-pub fn parse_hex(input: &str) -> Result<u32> {
-    Ok(u32::from_str_radix(input.trim_start_matches("0x"), 16)?)
+pub fn parse_overflow(input: &str) -> Result<Overflow> {
+    Ok(match input {
+        "visible" => Overflow::Visible,
+        "clip" => Overflow::Clip,
+        "marquee" => Overflow::Marquee,
+        _ => {
+            return Err(anyhow!(
+                "{} needs to be one of: 'visible', 'clip', 'marquee'",
+                input
+            ))
+        }
+    })
+}
+
+pub fn parse_marquee_mode(input: &str) -> Result<MarqueeMode> {
+    Ok(match input {
+        "loop" => MarqueeMode::Loop,
+        "bounce" => MarqueeMode::Bounce,
+        _ => return Err(anyhow!("{} needs to be one of: 'loop', 'bounce'", input)),
+    })
 }
 
 #[derive(Debug, StructOpt)]
@@ -42,18 +60,38 @@ pub fn parse_hex(input: &str) -> Result<u32> {
 struct CmdOptions {
     #[structopt(flatten)]
     style: common::style::StyleOpts,
-    /// Font
+    /// Font, can also come from a stylesheet
     #[structopt(short, long)]
-    font: String,
-    /// Font Size
+    font: Option<String>,
+    /// Font Size, can also come from a stylesheet
     #[structopt(short = "-z", long)]
-    font_size: f64,
+    font_size: Option<f64>,
     /// Text
     #[structopt(short, long)]
     text: String,
     /// Text color
-    #[structopt(short, long, parse(try_from_str = parse_hex))]
-    color: u32,
+    ///
+    /// As '#rrggbb', '#rrggbbaa', a named color, 'rgb(...)', 'hsl(...)' or
+    /// '$name' from the theme
+    #[structopt(short, long)]
+    color: Option<Color>,
+    /// What to do with text wider than its box
+    ///
+    /// One of 'visible', 'clip' or 'marquee' to scroll it
+    #[structopt(long, parse(try_from_str = parse_overflow))]
+    overflow: Option<Overflow>,
+    /// Speed of the marquee in points per second
+    #[structopt(long)]
+    marquee_speed: Option<f64>,
+    /// How long the marquee waits at the ends, in milliseconds
+    #[structopt(long)]
+    marquee_pause: Option<u64>,
+    /// Either 'loop' or 'bounce'
+    #[structopt(long, parse(try_from_str = parse_marquee_mode))]
+    marquee_mode: Option<MarqueeMode>,
+    /// Class to select the text by in stylesheets, can be given multiple times
+    #[structopt(long)]
+    class: Vec<String>,
 }
 
 fn main() -> Result<()> {
@@ -63,8 +101,27 @@ fn main() -> Result<()> {
         style: opt.style.to_style(),
         text: opt.text,
         font: opt.font,
-        color: piet::Color::from_rgba32_u32(opt.color),
+        color: opt.color,
         font_size: opt.font_size,
+        overflow: opt.overflow.unwrap_or_default(),
+        marquee: if opt.marquee_speed.is_some()
+            || opt.marquee_pause.is_some()
+            || opt.marquee_mode.is_some()
+        {
+            let default = Marquee::default();
+            Some(Marquee {
+                speed: opt.marquee_speed.unwrap_or(default.speed),
+                pause: opt.marquee_pause.unwrap_or(default.pause),
+                mode: opt.marquee_mode.unwrap_or(default.mode),
+                gap: default.gap,
+            })
+        } else {
+            None
+        },
+        attributes: Attributes {
+            class: opt.class,
+            ..Default::default()
+        },
     };
 
     println!("{}", to_string(&obj)?);
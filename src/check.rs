@@ -0,0 +1,507 @@
+use schemars::schema::{InstanceType, RootSchema, Schema, SchemaObject, SingleOrVec};
+use serde_json::Value;
+
+use crate::message::Message;
+
+/// Something wrong at a place in a message
+#[derive(Debug, Clone, PartialEq)]
+pub struct Problem {
+    /// A JSON pointer like `/0/children/2/style/alignItems`, empty for the
+    /// whole message
+    pub path: String,
+    pub message: String,
+}
+
+impl std::fmt::Display for Problem {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        if self.path.is_empty() {
+            f.write_str(&self.message)
+        } else {
+            write!(f, "{}: {}", self.path, self.message)
+        }
+    }
+}
+
+/// The problems of a message, listed on lines of their own if there are
+/// several
+#[derive(Debug)]
+pub struct Problems(pub Vec<Problem>);
+
+impl std::fmt::Display for Problems {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        if let [problem] = self.0.as_slice() {
+            return write!(f, "{}", problem);
+        }
+
+        write!(f, "{} problems", self.0.len())?;
+        for problem in &self.0 {
+            write!(f, "\n    {}", problem)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for Problems {}
+
+/// Checks messages against the schema printed by `viereck schema`
+///
+/// Unlike deserializing, this tells where in a message something is wrong.
+pub struct Checker {
+    root: RootSchema,
+    /// Properties the schema doesn't know are problems, otherwise only where
+    /// it rules them out, like in styles
+    strict: bool,
+}
+
+impl Checker {
+    pub fn new(strict: bool) -> Checker {
+        Checker {
+            root: schemars::schema_for!(Message),
+            strict,
+        }
+    }
+
+    /// Whether properties the schema doesn't know are problems
+    pub fn is_strict(&self) -> bool {
+        self.strict
+    }
+
+    /// Everything wrong with `value` as a message
+    pub fn check(&self, value: &Value) -> Vec<Problem> {
+        self.problems(value, self.strict)
+    }
+
+    /// `value` deserialized, with the problems found in it as the context of
+    /// an error
+    ///
+    /// `value` needs to be a message, like an array of objects.
+    pub fn decode<T: serde::de::DeserializeOwned>(&self, value: Value) -> anyhow::Result<T> {
+        if self.strict {
+            let problems = self.check(&value);
+            if !problems.is_empty() {
+                return Err(Problems(problems).into());
+            }
+        }
+
+        T::deserialize(&value).map_err(|e| {
+            let problems = self.problems(&value, false);
+            if problems.is_empty() {
+                e.into()
+            } else {
+                anyhow::Error::new(e).context(Problems(problems))
+            }
+        })
+    }
+
+    fn problems(&self, value: &Value, strict: bool) -> Vec<Problem> {
+        let mut check = Check {
+            root: &self.root,
+            strict,
+            problems: vec![],
+        };
+        check.object(&self.root.schema, value, "");
+        check.problems
+    }
+}
+
+/// A single run of a `Checker`
+struct Check<'a> {
+    root: &'a RootSchema,
+    strict: bool,
+    problems: Vec<Problem>,
+}
+
+impl<'a> Check<'a> {
+    fn report(&mut self, path: &str, message: String) {
+        self.problems.push(Problem {
+            path: path.to_string(),
+            message,
+        });
+    }
+
+    /// The schema a `$ref` points to, or `schema` itself
+    fn resolve(&self, schema: &'a Schema) -> Option<&'a SchemaObject> {
+        match schema {
+            Schema::Object(SchemaObject {
+                reference: Some(reference),
+                ..
+            }) => {
+                let name = reference.trim_start_matches("#/definitions/");
+                self.resolve(self.root.definitions.get(name)?)
+            }
+            Schema::Object(object) => Some(object),
+            Schema::Bool(_) => None,
+        }
+    }
+
+    fn schema(&mut self, schema: &'a Schema, value: &Value, path: &str) {
+        match schema {
+            Schema::Bool(true) => {}
+            Schema::Bool(false) => self.report(path, "Not allowed here".to_string()),
+            Schema::Object(object) => self.object(object, value, path),
+        }
+    }
+
+    fn object(&mut self, schema: &'a SchemaObject, value: &Value, path: &str) {
+        if let Some(reference) = &schema.reference {
+            let name = reference.trim_start_matches("#/definitions/");
+            if let Some(schema) = self.root.definitions.get(name) {
+                self.schema(schema, value, path);
+            }
+            return;
+        }
+
+        if let Some(subschemas) = &schema.subschemas {
+            for schema in subschemas.all_of.iter().flatten() {
+                self.schema(schema, value, path);
+            }
+            if let Some(alternatives) = subschemas.any_of.as_ref().or(subschemas.one_of.as_ref()) {
+                self.alternatives(alternatives, value, path);
+            }
+        }
+
+        if let Some(types) = &schema.instance_type {
+            let types: &[InstanceType] = match types {
+                SingleOrVec::Single(single) => std::slice::from_ref(single),
+                SingleOrVec::Vec(types) => types,
+            };
+            if !types.iter().any(|&expected| has_type(value, expected)) {
+                let expected: Vec<_> = types.iter().map(|&t| describe(t)).collect();
+                self.report(
+                    path,
+                    format!(
+                        "Expected {}, found {}",
+                        expected.join(" or "),
+                        describe(type_of(value))
+                    ),
+                );
+                return;
+            }
+        }
+
+        let allowed = schema
+            .enum_values
+            .as_deref()
+            .or_else(|| schema.const_value.as_ref().map(std::slice::from_ref));
+        if let Some(allowed) = allowed {
+            if !allowed.contains(value) {
+                let message = not_one_of(allowed, value);
+                self.report(path, message);
+                return;
+            }
+        }
+
+        if let (Some(number), Some(found)) = (&schema.number, value.as_f64()) {
+            if let Some(minimum) = number.minimum.filter(|&minimum| found < minimum) {
+                self.report(path, format!("Needs to be at least {}", minimum));
+            }
+            if let Some(maximum) = number.maximum.filter(|&maximum| found > maximum) {
+                self.report(path, format!("Needs to be at most {}", maximum));
+            }
+        }
+
+        match value {
+            Value::Object(properties) => {
+                if let Some(object) = &schema.object {
+                    for name in &object.required {
+                        if !properties.contains_key(name) {
+                            self.report(path, format!("Missing `{}`", name));
+                        }
+                    }
+
+                    for (name, value) in properties {
+                        let path = child(path, name);
+                        if let Some(schema) = object.properties.get(name) {
+                            self.schema(schema, value, &path);
+                            continue;
+                        }
+
+                        match object.additional_properties.as_deref() {
+                            Some(Schema::Bool(false)) => self.unknown(&object.properties, &path),
+                            Some(schema) => self.schema(schema, value, &path),
+                            None if self.strict && !object.properties.is_empty() => {
+                                self.unknown(&object.properties, &path)
+                            }
+                            None => {}
+                        }
+                    }
+                }
+            }
+            Value::Array(items) => {
+                match schema.array.as_ref().and_then(|array| array.items.as_ref()) {
+                    Some(SingleOrVec::Single(schema)) => {
+                        for (index, item) in items.iter().enumerate() {
+                            self.schema(schema, item, &child(path, &index.to_string()));
+                        }
+                    }
+                    Some(SingleOrVec::Vec(schemas)) => {
+                        for (index, (schema, item)) in schemas.iter().zip(items).enumerate() {
+                            self.schema(schema, item, &child(path, &index.to_string()));
+                        }
+                    }
+                    None => {}
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn unknown(&mut self, known: &schemars::Map<String, Schema>, path: &str) {
+        let name = path.rsplit('/').next().unwrap_or_default();
+        let message = match suggestion(&unescape(name), known.keys().map(String::as_str)) {
+            Some(known) => format!("Unknown property, did you mean `{}`?", known),
+            None => "Unknown property".to_string(),
+        };
+        self.report(path, message);
+    }
+
+    /// Checks `value` against the alternative it fits best
+    fn alternatives(&mut self, alternatives: &'a [Schema], value: &Value, path: &str) {
+        // Kinds of objects are told apart by a tag, like their `type`
+        if let (Value::Object(properties), Some((tag, kinds))) = (value, self.tag(alternatives)) {
+            let kind = match properties.get(tag) {
+                Some(kind) => kind,
+                None => {
+                    let message = format!("Missing `{}`, one of {}", tag, quoted(&kinds));
+                    self.report(path, message);
+                    return;
+                }
+            };
+            match kinds.iter().position(|&known| known == kind) {
+                Some(index) => self.schema(&alternatives[index], value, path),
+                None => {
+                    let message = not_one_of(&kinds.into_iter().cloned().collect::<Vec<_>>(), kind);
+                    self.report(&child(path, tag), message);
+                }
+            }
+            return;
+        }
+
+        // Otherwise the one with the fewest problems about `value` itself
+        // instead of what is in it, as those mean it's not the one meant
+        let mut best: Option<(usize, Vec<Problem>)> = None;
+        for alternative in alternatives {
+            let mut check = Check {
+                root: self.root,
+                strict: self.strict,
+                problems: vec![],
+            };
+            check.schema(alternative, value, path);
+            if check.problems.is_empty() {
+                return;
+            }
+
+            let rank = check.problems.iter().filter(|p| p.path == path).count();
+            let better = match &best {
+                Some((best_rank, best_problems)) => {
+                    (rank, check.problems.len()) < (*best_rank, best_problems.len())
+                }
+                None => true,
+            };
+            if better {
+                best = Some((rank, check.problems));
+            }
+        }
+
+        if let Some((_, problems)) = best {
+            self.problems.extend(problems);
+        }
+    }
+
+    /// The required property every alternative has a different fixed value
+    /// of, along with those values
+    fn tag(&self, alternatives: &'a [Schema]) -> Option<(&'a str, Vec<&'a Value>)> {
+        let objects: Vec<_> = alternatives
+            .iter()
+            .map(|alternative| self.resolve(alternative)?.object.as_deref())
+            .collect::<Option<_>>()?;
+
+        objects.first()?.required.iter().find_map(|tag| {
+            let kinds = objects
+                .iter()
+                .map(|object| {
+                    if !object.required.contains(tag) {
+                        return None;
+                    }
+                    let kind = self.resolve(object.properties.get(tag)?)?;
+                    match (&kind.enum_values, &kind.const_value) {
+                        (Some(values), _) if values.len() == 1 => values.first(),
+                        (_, Some(value)) => Some(value),
+                        _ => None,
+                    }
+                })
+                .collect::<Option<Vec<_>>>()?;
+            Some((tag.as_str(), kinds))
+        })
+    }
+}
+
+/// `path` with the JSON pointer token for `name` added
+fn child(path: &str, name: &str) -> String {
+    format!("{}/{}", path, name.replace('~', "~0").replace('/', "~1"))
+}
+
+fn unescape(token: &str) -> String {
+    token.replace("~1", "/").replace("~0", "~")
+}
+
+fn type_of(value: &Value) -> InstanceType {
+    match value {
+        Value::Null => InstanceType::Null,
+        Value::Bool(_) => InstanceType::Boolean,
+        Value::Number(number) if number.is_f64() => InstanceType::Number,
+        Value::Number(_) => InstanceType::Integer,
+        Value::String(_) => InstanceType::String,
+        Value::Array(_) => InstanceType::Array,
+        Value::Object(_) => InstanceType::Object,
+    }
+}
+
+fn has_type(value: &Value, expected: InstanceType) -> bool {
+    let found = type_of(value);
+    found == expected || (expected == InstanceType::Number && found == InstanceType::Integer)
+}
+
+fn describe(instance_type: InstanceType) -> &'static str {
+    match instance_type {
+        InstanceType::Null => "null",
+        InstanceType::Boolean => "a boolean",
+        InstanceType::Object => "an object",
+        InstanceType::Array => "an array",
+        InstanceType::Number => "a number",
+        InstanceType::String => "a string",
+        InstanceType::Integer => "an integer",
+    }
+}
+
+/// Strings as they are, everything else as JSON
+fn show(value: &Value) -> String {
+    match value {
+        Value::String(string) => string.clone(),
+        value => value.to_string(),
+    }
+}
+
+fn quoted(values: &[&Value]) -> String {
+    let values: Vec<_> = values
+        .iter()
+        .map(|value| format!("`{}`", show(value)))
+        .collect();
+    values.join(", ")
+}
+
+fn not_one_of(allowed: &[Value], found: &Value) -> String {
+    let mut message = format!(
+        "Expected one of {}, found `{}`",
+        quoted(&allowed.iter().collect::<Vec<_>>()),
+        show(found)
+    );
+    if let Some(known) = found
+        .as_str()
+        .and_then(|found| suggestion(found, allowed.iter().filter_map(Value::as_str)))
+    {
+        message.push_str(&format!(", did you mean `{}`?", known));
+    }
+    message
+}
+
+/// The known name `name` is most likely a typo of
+fn suggestion<'k>(name: &str, known: impl IntoIterator<Item = &'k str>) -> Option<&'k str> {
+    // Case and separators are ignored, `align_items` is `alignItems`
+    fn normalize(name: &str) -> String {
+        name.chars()
+            .filter(|c| *c != '_' && *c != '-')
+            .flat_map(char::to_lowercase)
+            .collect()
+    }
+
+    let name = normalize(name);
+    known
+        .into_iter()
+        .map(|known| (strsim::jaro_winkler(&name, &normalize(known)), known))
+        .filter(|(similarity, _)| *similarity > 0.8)
+        .max_by(|(a, _), (b, _)| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal))
+        .map(|(_, known)| known)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn problems(strict: bool, input: &str) -> Vec<String> {
+        let value = serde_json::from_str(input).unwrap();
+        Checker::new(strict)
+            .check(&value)
+            .iter()
+            .map(ToString::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn unknown_style_key() {
+        let input = r#"[{"type": "Container", "style": {}, "children": [
+            {"type": "Text", "text": "a", "style": {}},
+            {"type": "Text", "text": "b", "style": {"align_items": "center"}}
+        ]}]"#;
+        let expected = vec![
+            "/0/children/1/style/align_items: Unknown property, did you mean `alignItems`?"
+                .to_string(),
+        ];
+
+        // Styles rule out unknown properties even when not strict
+        assert_eq!(problems(false, input), expected);
+        assert_eq!(problems(true, input), expected);
+    }
+
+    #[test]
+    fn bad_type_tag() {
+        let misspelled = problems(false, r#"[{"type": "Txt", "text": "a", "style": {}}]"#);
+        assert_eq!(misspelled.len(), 1);
+        assert!(misspelled[0].starts_with("/0/type: Expected one of `Container`, "));
+        assert!(misspelled[0].ends_with("found `Txt`, did you mean `Text`?"));
+
+        let missing = problems(false, r#"[{"text": "a", "style": {}}]"#);
+        assert_eq!(missing.len(), 1);
+        assert!(missing[0].starts_with("/0: Missing `type`, one of `Container`, "));
+
+        let not_a_string = problems(false, r#"[{"type": 5, "text": "a", "style": {}}]"#);
+        assert_eq!(not_a_string.len(), 1);
+        assert!(not_a_string[0].starts_with("/0/type: Expected one of "));
+        assert!(not_a_string[0].ends_with("found `5`"));
+    }
+
+    #[test]
+    fn escaping() {
+        assert_eq!(child("", "text"), "/text");
+        assert_eq!(child("/0", "a/b~c"), "/0/a~1b~0c");
+        assert_eq!(child("/0", "~1"), "/0/~01");
+        assert_eq!(unescape("a~1b~0c"), "a/b~c");
+        assert_eq!(unescape("~01"), "~1");
+
+        let found = problems(
+            false,
+            r#"[{"type": "Text", "text": "a", "style": {"flex/grow~": 1}}]"#,
+        );
+        assert_eq!(found.len(), 1);
+        assert!(found[0].starts_with("/0/style/flex~1grow~0: Unknown property"));
+    }
+
+    #[test]
+    fn strict_unknown_keys() {
+        let input = r#"[{"type": "Text", "text": "a", "txet": "b", "style": {}}]"#;
+
+        assert_eq!(problems(false, input), Vec::<String>::new());
+        assert_eq!(
+            problems(true, input),
+            vec!["/0/txet: Unknown property, did you mean `text`?".to_string()]
+        );
+
+        let value: Value = serde_json::from_str(input).unwrap();
+        assert!(Checker::new(false).decode::<Message>(value.clone()).is_ok());
+        let error = Checker::new(true).decode::<Message>(value).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "/0/txet: Unknown property, did you mean `text`?"
+        );
+    }
+}
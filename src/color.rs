@@ -0,0 +1,554 @@
+//! Parsing of colors written like in CSS
+//!
+//! Supported are `#rgb`, `#rgba`, `#rrggbb`, `#rrggbbaa`, the CSS named
+//! colors, `rgb()`/`rgba()` and `hsl()`/`hsla()`. The `0xrrggbbaa` form the
+//! command line tools used to take is accepted as well. Colors can also refer
+//! to an entry of the `Theme` as `$name`.
+
+use anyhow::{anyhow, Context, Result};
+use serde::de::Error as _;
+use serde_derive::Deserialize;
+
+/// The CSS named colors, as `0xrrggbb`
+const NAMED_COLORS: &[(&str, u32)] = &[
+    ("aliceblue", 0xf0f8ff),
+    ("antiquewhite", 0xfaebd7),
+    ("aqua", 0x00ffff),
+    ("aquamarine", 0x7fffd4),
+    ("azure", 0xf0ffff),
+    ("beige", 0xf5f5dc),
+    ("bisque", 0xffe4c4),
+    ("black", 0x000000),
+    ("blanchedalmond", 0xffebcd),
+    ("blue", 0x0000ff),
+    ("blueviolet", 0x8a2be2),
+    ("brown", 0xa52a2a),
+    ("burlywood", 0xdeb887),
+    ("cadetblue", 0x5f9ea0),
+    ("chartreuse", 0x7fff00),
+    ("chocolate", 0xd2691e),
+    ("coral", 0xff7f50),
+    ("cornflowerblue", 0x6495ed),
+    ("cornsilk", 0xfff8dc),
+    ("crimson", 0xdc143c),
+    ("cyan", 0x00ffff),
+    ("darkblue", 0x00008b),
+    ("darkcyan", 0x008b8b),
+    ("darkgoldenrod", 0xb8860b),
+    ("darkgray", 0xa9a9a9),
+    ("darkgreen", 0x006400),
+    ("darkgrey", 0xa9a9a9),
+    ("darkkhaki", 0xbdb76b),
+    ("darkmagenta", 0x8b008b),
+    ("darkolivegreen", 0x556b2f),
+    ("darkorange", 0xff8c00),
+    ("darkorchid", 0x9932cc),
+    ("darkred", 0x8b0000),
+    ("darksalmon", 0xe9967a),
+    ("darkseagreen", 0x8fbc8f),
+    ("darkslateblue", 0x483d8b),
+    ("darkslategray", 0x2f4f4f),
+    ("darkslategrey", 0x2f4f4f),
+    ("darkturquoise", 0x00ced1),
+    ("darkviolet", 0x9400d3),
+    ("deeppink", 0xff1493),
+    ("deepskyblue", 0x00bfff),
+    ("dimgray", 0x696969),
+    ("dimgrey", 0x696969),
+    ("dodgerblue", 0x1e90ff),
+    ("firebrick", 0xb22222),
+    ("floralwhite", 0xfffaf0),
+    ("forestgreen", 0x228b22),
+    ("fuchsia", 0xff00ff),
+    ("gainsboro", 0xdcdcdc),
+    ("ghostwhite", 0xf8f8ff),
+    ("gold", 0xffd700),
+    ("goldenrod", 0xdaa520),
+    ("gray", 0x808080),
+    ("green", 0x008000),
+    ("greenyellow", 0xadff2f),
+    ("grey", 0x808080),
+    ("honeydew", 0xf0fff0),
+    ("hotpink", 0xff69b4),
+    ("indianred", 0xcd5c5c),
+    ("indigo", 0x4b0082),
+    ("ivory", 0xfffff0),
+    ("khaki", 0xf0e68c),
+    ("lavender", 0xe6e6fa),
+    ("lavenderblush", 0xfff0f5),
+    ("lawngreen", 0x7cfc00),
+    ("lemonchiffon", 0xfffacd),
+    ("lightblue", 0xadd8e6),
+    ("lightcoral", 0xf08080),
+    ("lightcyan", 0xe0ffff),
+    ("lightgoldenrodyellow", 0xfafad2),
+    ("lightgray", 0xd3d3d3),
+    ("lightgreen", 0x90ee90),
+    ("lightgrey", 0xd3d3d3),
+    ("lightpink", 0xffb6c1),
+    ("lightsalmon", 0xffa07a),
+    ("lightseagreen", 0x20b2aa),
+    ("lightskyblue", 0x87cefa),
+    ("lightslategray", 0x778899),
+    ("lightslategrey", 0x778899),
+    ("lightsteelblue", 0xb0c4de),
+    ("lightyellow", 0xffffe0),
+    ("lime", 0x00ff00),
+    ("limegreen", 0x32cd32),
+    ("linen", 0xfaf0e6),
+    ("magenta", 0xff00ff),
+    ("maroon", 0x800000),
+    ("mediumaquamarine", 0x66cdaa),
+    ("mediumblue", 0x0000cd),
+    ("mediumorchid", 0xba55d3),
+    ("mediumpurple", 0x9370db),
+    ("mediumseagreen", 0x3cb371),
+    ("mediumslateblue", 0x7b68ee),
+    ("mediumspringgreen", 0x00fa9a),
+    ("mediumturquoise", 0x48d1cc),
+    ("mediumvioletred", 0xc71585),
+    ("midnightblue", 0x191970),
+    ("mintcream", 0xf5fffa),
+    ("mistyrose", 0xffe4e1),
+    ("moccasin", 0xffe4b5),
+    ("navajowhite", 0xffdead),
+    ("navy", 0x000080),
+    ("oldlace", 0xfdf5e6),
+    ("olive", 0x808000),
+    ("olivedrab", 0x6b8e23),
+    ("orange", 0xffa500),
+    ("orangered", 0xff4500),
+    ("orchid", 0xda70d6),
+    ("palegoldenrod", 0xeee8aa),
+    ("palegreen", 0x98fb98),
+    ("paleturquoise", 0xafeeee),
+    ("palevioletred", 0xdb7093),
+    ("papayawhip", 0xffefd5),
+    ("peachpuff", 0xffdab9),
+    ("peru", 0xcd853f),
+    ("pink", 0xffc0cb),
+    ("plum", 0xdda0dd),
+    ("powderblue", 0xb0e0e6),
+    ("purple", 0x800080),
+    ("rebeccapurple", 0x663399),
+    ("red", 0xff0000),
+    ("rosybrown", 0xbc8f8f),
+    ("royalblue", 0x4169e1),
+    ("saddlebrown", 0x8b4513),
+    ("salmon", 0xfa8072),
+    ("sandybrown", 0xf4a460),
+    ("seagreen", 0x2e8b57),
+    ("seashell", 0xfff5ee),
+    ("sienna", 0xa0522d),
+    ("silver", 0xc0c0c0),
+    ("skyblue", 0x87ceeb),
+    ("slateblue", 0x6a5acd),
+    ("slategray", 0x708090),
+    ("slategrey", 0x708090),
+    ("snow", 0xfffafa),
+    ("springgreen", 0x00ff7f),
+    ("steelblue", 0x4682b4),
+    ("tan", 0xd2b48c),
+    ("teal", 0x008080),
+    ("thistle", 0xd8bfd8),
+    ("tomato", 0xff6347),
+    ("turquoise", 0x40e0d0),
+    ("violet", 0xee82ee),
+    ("wheat", 0xf5deb3),
+    ("white", 0xffffff),
+    ("whitesmoke", 0xf5f5f5),
+    ("yellow", 0xffff00),
+    ("yellowgreen", 0x9acd32),
+];
+
+/// Parse a color in any of the supported syntaxes
+pub fn parse(input: &str) -> Result<piet::Color> {
+    let input = input.trim();
+    let lower = input.to_ascii_lowercase();
+
+    if let Some(hex) = lower.strip_prefix("#") {
+        return parse_hex(hex).with_context(|| format!("{} is not a valid hex color", input));
+    }
+
+    if let Some(hex) = lower.strip_prefix("0x") {
+        return u32::from_str_radix(hex, 16)
+            .map(piet::Color::from_rgba32_u32)
+            .with_context(|| format!("{} is not a valid 0xrrggbbaa color", input));
+    }
+
+    if let Some(args) = function_args(&lower, &["rgb", "rgba"]) {
+        return parse_rgb(args).with_context(|| format!("{} is not a valid rgb() color", input));
+    }
+
+    if let Some(args) = function_args(&lower, &["hsl", "hsla"]) {
+        return parse_hsl(args).with_context(|| format!("{} is not a valid hsl() color", input));
+    }
+
+    if lower == "transparent" {
+        return Ok(piet::Color::from_rgba32_u32(0));
+    }
+
+    NAMED_COLORS
+        .iter()
+        .find(|(name, _)| *name == lower)
+        .map(|(_, rgb)| piet::Color::from_rgba32_u32(rgb << 8 | 0xFF))
+        .ok_or_else(|| {
+            anyhow!(
+                "{} is not a color, expected '#rrggbb', '#rrggbbaa', a named color, rgb() or hsl()",
+                input
+            )
+        })
+}
+
+/// Format a color as `#rrggbbaa`
+pub fn to_hex(color: &piet::Color) -> String {
+    format!("#{:08x}", color.as_rgba_u32())
+}
+
+/// A color as given in the input, either directly or by its name in the theme
+#[derive(Debug, Clone)]
+pub enum Color {
+    Value(piet::Color),
+    /// Written as `$name`
+    Var(String),
+}
+
+impl From<piet::Color> for Color {
+    fn from(color: piet::Color) -> Color {
+        Color::Value(color)
+    }
+}
+
+impl std::str::FromStr for Color {
+    type Err = anyhow::Error;
+
+    fn from_str(input: &str) -> Result<Color> {
+        match input.trim().strip_prefix('$') {
+            Some(name) => Ok(Color::Var(name.to_string())),
+            None => parse(input).map(Color::Value),
+        }
+    }
+}
+
+impl serde::Serialize for Color {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Color::Value(color) => serializer.serialize_str(&to_hex(color)),
+            Color::Var(name) => serializer.serialize_str(&format!("${}", name)),
+        }
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for Color {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Repr {
+            Css(String),
+            Legacy {
+                #[serde(rename = "Rgba32")]
+                rgba32: u32,
+            },
+        }
+
+        match <Repr as serde::Deserialize>::deserialize(deserializer)? {
+            Repr::Css(color) => color.parse().map_err(D::Error::custom),
+            Repr::Legacy { rgba32 } => Ok(Color::Value(piet::Color::from_rgba32_u32(rgba32))),
+        }
+    }
+}
+
+impl schemars::JsonSchema for Color {
+    fn schema_name() -> String {
+        "Color".to_string()
+    }
+
+    fn json_schema(_: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+        schema(
+            "'#rrggbb', '#rrggbbaa', '#rgb', '#rgba', a named color, 'rgb(...)', \
+             'rgba(...)', 'hsl(...)', 'hsla(...)', 'transparent' or '$name' from the theme",
+        )
+    }
+}
+
+/// The schema of a color written in any of the syntaxes described by
+/// `description` or the older `{"Rgba32": 4278190335}` form
+pub(crate) fn schema(description: &str) -> schemars::schema::Schema {
+    use schemars::schema::{InstanceType, SchemaObject, SubschemaValidation};
+
+    let mut css = SchemaObject {
+        instance_type: Some(InstanceType::String.into()),
+        ..Default::default()
+    };
+    css.metadata().description = Some(description.to_string());
+
+    let mut legacy = SchemaObject {
+        instance_type: Some(InstanceType::Object.into()),
+        ..Default::default()
+    };
+    let rgba32 = SchemaObject {
+        instance_type: Some(InstanceType::Integer.into()),
+        ..Default::default()
+    };
+    legacy
+        .object()
+        .properties
+        .insert("Rgba32".to_string(), rgba32.into());
+    legacy.object().required.insert("Rgba32".to_string());
+
+    SchemaObject {
+        subschemas: Some(Box::new(SubschemaValidation {
+            any_of: Some(vec![css.into(), legacy.into()]),
+            ..Default::default()
+        })),
+        ..Default::default()
+    }
+    .into()
+}
+
+/// The arguments of `name(...)` for any of the given names
+fn function_args<'a>(input: &'a str, names: &[&str]) -> Option<&'a str> {
+    let open = input.find('(')?;
+    if !names.contains(&input[..open].trim()) || !input.ends_with(')') {
+        return None;
+    }
+    Some(&input[open + 1..input.len() - 1])
+}
+
+fn parse_hex(hex: &str) -> Result<piet::Color> {
+    if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(anyhow!("Only hex digits are allowed"));
+    }
+
+    // Short forms repeat each digit, `#abc` is `#aabbcc`
+    let expanded: String = match hex.len() {
+        3 | 4 => hex.chars().flat_map(|c| vec![c, c]).collect(),
+        6 | 8 => hex.to_string(),
+        _ => return Err(anyhow!("Expected 3, 4, 6 or 8 digits")),
+    };
+
+    let value = u32::from_str_radix(&expanded, 16)?;
+    Ok(piet::Color::from_rgba32_u32(if expanded.len() == 6 {
+        value << 8 | 0xFF
+    } else {
+        value
+    }))
+}
+
+/// Split the arguments of a color function, which can be separated by commas
+/// or by spaces with the alpha after a slash
+fn split_args(args: &str) -> Vec<&str> {
+    args.split(|c: char| c == ',' || c == '/' || c.is_whitespace())
+        .filter(|arg| !arg.is_empty())
+        .collect()
+}
+
+/// A number, or a percentage of `full`
+fn parse_component(arg: &str, full: f64) -> Result<f64> {
+    match arg.strip_suffix("%") {
+        Some(percent) => Ok(percent.parse::<f64>()? / 100. * full),
+        None => Ok(arg.parse::<f64>()?),
+    }
+}
+
+fn parse_alpha(args: &[&str], index: usize) -> Result<f64> {
+    match args.get(index) {
+        Some(alpha) => parse_component(alpha, 1.),
+        None => Ok(1.),
+    }
+}
+
+fn from_floats(red: f64, green: f64, blue: f64, alpha: f64) -> piet::Color {
+    let channel = |value: f64| value.max(0.).min(255.).round() as u32;
+    piet::Color::from_rgba32_u32(
+        channel(red) << 24 | channel(green) << 16 | channel(blue) << 8 | channel(alpha * 255.),
+    )
+}
+
+fn parse_rgb(args: &str) -> Result<piet::Color> {
+    let args = split_args(args);
+    if args.len() != 3 && args.len() != 4 {
+        return Err(anyhow!("Expected 3 or 4 arguments"));
+    }
+
+    Ok(from_floats(
+        parse_component(args[0], 255.)?,
+        parse_component(args[1], 255.)?,
+        parse_component(args[2], 255.)?,
+        parse_alpha(&args, 3)?,
+    ))
+}
+
+fn parse_hsl(args: &str) -> Result<piet::Color> {
+    let args = split_args(args);
+    if args.len() != 3 && args.len() != 4 {
+        return Err(anyhow!("Expected 3 or 4 arguments"));
+    }
+
+    let hue = match args[0].strip_suffix("turn") {
+        Some(turns) => turns.parse::<f64>()? * 360.,
+        None => args[0]
+            .strip_suffix("deg")
+            .unwrap_or(args[0])
+            .parse::<f64>()?,
+    };
+    let saturation = parse_component(args[1], 1.)?.max(0.).min(1.);
+    let lightness = parse_component(args[2], 1.)?.max(0.).min(1.);
+
+    // See https://www.w3.org/TR/css-color-3/#hsl-color
+    let hue = hue.rem_euclid(360.) / 360.;
+    let high = if lightness <= 0.5 {
+        lightness * (saturation + 1.)
+    } else {
+        lightness + saturation - lightness * saturation
+    };
+    let low = lightness * 2. - high;
+    let hue_to_rgb = |hue: f64| {
+        let hue = hue.rem_euclid(1.);
+        if hue * 6. < 1. {
+            low + (high - low) * hue * 6.
+        } else if hue * 2. < 1. {
+            high
+        } else if hue * 3. < 2. {
+            low + (high - low) * (2. / 3. - hue) * 6.
+        } else {
+            low
+        }
+    };
+
+    Ok(from_floats(
+        hue_to_rgb(hue + 1. / 3.) * 255.,
+        hue_to_rgb(hue) * 255.,
+        hue_to_rgb(hue - 1. / 3.) * 255.,
+        parse_alpha(&args, 3)?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rgba(input: &str) -> u32 {
+        parse(input)
+            .unwrap_or_else(|e| panic!("{} did not parse: {:#}", input, e))
+            .as_rgba_u32()
+    }
+
+    #[test]
+    fn hex() {
+        for (input, expected) in &[
+            ("#f80", 0xff8800ff),
+            ("#F80", 0xff8800ff),
+            ("#f808", 0xff880088),
+            ("#ff6347", 0xff6347ff),
+            ("#ff634780", 0xff634780),
+            (" #ff6347 ", 0xff6347ff),
+            ("0xff634780", 0xff634780),
+        ] {
+            assert_eq!(rgba(input), *expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn named() {
+        for (input, expected) in &[
+            ("tomato", 0xff6347ff),
+            ("Tomato", 0xff6347ff),
+            ("rebeccapurple", 0x663399ff),
+            ("black", 0x000000ff),
+            ("transparent", 0x00000000),
+        ] {
+            assert_eq!(rgba(input), *expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn rgb() {
+        for (input, expected) in &[
+            ("rgb(255, 99, 71)", 0xff6347ff),
+            ("rgb(255 99 71)", 0xff6347ff),
+            ("rgb(100%, 0%, 50%)", 0xff0080ff),
+            ("rgb(300, -5, 71)", 0xff0047ff),
+            ("rgba(255, 99, 71, 0.5)", 0xff634780),
+            ("rgba(255, 99, 71, 50%)", 0xff634780),
+            ("rgb(255 99 71 / 0)", 0xff634700),
+            ("RGB(255, 99, 71)", 0xff6347ff),
+        ] {
+            assert_eq!(rgba(input), *expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn hsl() {
+        for (input, expected) in &[
+            ("hsl(0, 100%, 50%)", 0xff0000ff),
+            ("hsl(120, 100%, 50%)", 0x00ff00ff),
+            ("hsl(240deg, 100%, 50%)", 0x0000ffff),
+            ("hsl(0.5turn, 100%, 50%)", 0x00ffffff),
+            ("hsl(-120, 100%, 50%)", 0x0000ffff),
+            ("hsl(9, 100%, 64%)", 0xff6347ff),
+            ("hsl(0, 0%, 100%)", 0xffffffff),
+            ("hsla(120, 100%, 25%, 0.5)", 0x00800080),
+            ("hsl(120 100% 25% / 50%)", 0x00800080),
+        ] {
+            assert_eq!(rgba(input), *expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn invalid() {
+        for input in &[
+            "",
+            "#",
+            "#ff",
+            "#fffff",
+            "#ggg",
+            "#ff634780ff",
+            "0xzz",
+            "rgb()",
+            "rgb(1, 2)",
+            "rgb(1, 2, 3, 4, 5)",
+            "rgb(a, b, c)",
+            "rgb(1, 2, 3",
+            "hsl(0, 100%)",
+            "hsl(red, 100%, 50%)",
+            "hsla(0, 100%, 50%, x)",
+            "notacolor",
+        ] {
+            assert!(parse(input).is_err(), "{} should not parse", input);
+        }
+    }
+
+    #[test]
+    fn theme_var() {
+        match "$accent".parse::<Color>().unwrap() {
+            Color::Var(name) => assert_eq!(name, "accent"),
+            color => panic!("{:?} is not a theme color", color),
+        }
+    }
+
+    #[test]
+    fn deserialize() {
+        for (input, expected) in &[
+            (r##""#ff6347""##, 0xff6347ff),
+            (r#""tomato""#, 0xff6347ff),
+            (r#""rgba(255, 99, 71, 0.5)""#, 0xff634780),
+            (r#"{"Rgba32": 4284696575}"#, 4284696575),
+        ] {
+            match serde_json::from_str::<Color>(input).unwrap() {
+                Color::Value(color) => assert_eq!(color.as_rgba_u32(), *expected, "{}", input),
+                color => panic!("{} parsed as {:?}", input, color),
+            }
+        }
+
+        for input in &[r#""nope""#, r#"{"Rgba32": "red"}"#, "42", "null"] {
+            assert!(serde_json::from_str::<Color>(input).is_err(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn hex_round_trip() {
+        assert_eq!(to_hex(&parse("#ff634780").unwrap()), "#ff634780");
+        assert_eq!(to_hex(&parse("tomato").unwrap()), "#ff6347ff");
+    }
+}
@@ -0,0 +1,320 @@
+//! Icon lookup following the freedesktop Icon Theme Specification
+//!
+//! See https://specifications.freedesktop.org/icon-theme-spec/latest/ for the
+//! algorithm this implements.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+/// The theme every other theme falls back to
+pub const FALLBACK_THEME: &str = "hicolor";
+
+const EXTENSIONS: &[&str] = &["png", "svg"];
+
+thread_local! {
+    static THEMES: RefCell<HashMap<String, Option<Rc<Theme>>>> = RefCell::new(HashMap::new());
+    static ICONS: RefCell<HashMap<(String, u32, Option<String>), PathBuf>> =
+        RefCell::new(HashMap::new());
+    static USER_THEME: Option<String> = user_theme();
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum DirectoryType {
+    Fixed,
+    Scalable,
+    Threshold,
+}
+
+#[derive(Debug)]
+struct Directory {
+    path: String,
+    size: u32,
+    scale: u32,
+    kind: DirectoryType,
+    min_size: u32,
+    max_size: u32,
+    threshold: u32,
+}
+
+impl Directory {
+    fn matches_size(&self, size: u32) -> bool {
+        if self.scale != 1 {
+            return false;
+        }
+
+        match self.kind {
+            DirectoryType::Fixed => self.size == size,
+            DirectoryType::Scalable => self.min_size <= size && size <= self.max_size,
+            DirectoryType::Threshold => {
+                self.size.saturating_sub(self.threshold) <= size
+                    && size <= self.size + self.threshold
+            }
+        }
+    }
+
+    fn size_distance(&self, size: u32) -> u32 {
+        let (min, max) = match self.kind {
+            DirectoryType::Fixed => (self.size, self.size),
+            DirectoryType::Scalable => (self.min_size, self.max_size),
+            DirectoryType::Threshold => (
+                self.size.saturating_sub(self.threshold),
+                self.size + self.threshold,
+            ),
+        };
+
+        if size < min * self.scale {
+            min * self.scale - size
+        } else {
+            size.saturating_sub(max * self.scale)
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Theme {
+    name: String,
+    parents: Vec<String>,
+    directories: Vec<Directory>,
+}
+
+impl Theme {
+    fn load(name: &str) -> Option<Rc<Theme>> {
+        if let Some(theme) = THEMES.with(|themes| themes.borrow().get(name).cloned()) {
+            return theme;
+        }
+
+        let theme = base_directories()
+            .iter()
+            .map(|dir| dir.join(name).join("index.theme"))
+            .find_map(|index| std::fs::read_to_string(index).ok())
+            .map(|index| Rc::new(Theme::parse(name, &index)));
+
+        THEMES.with(|themes| themes.borrow_mut().insert(name.to_string(), theme.clone()));
+        theme
+    }
+
+    fn parse(name: &str, index: &str) -> Theme {
+        let mut sections: HashMap<&str, HashMap<&str, &str>> = HashMap::new();
+        let mut current = None;
+
+        for line in index.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            if line.starts_with('[') && line.ends_with(']') {
+                let section = &line[1..line.len() - 1];
+                sections.entry(section).or_default();
+                current = Some(section);
+            } else if let (Some(section), Some(idx)) = (current, line.find('=')) {
+                let (key, value) = (line[..idx].trim(), line[idx + 1..].trim());
+                sections.entry(section).or_default().insert(key, value);
+            }
+        }
+
+        let empty = HashMap::new();
+        let theme = sections.get("Icon Theme").unwrap_or(&empty);
+        let list = |key: &str| -> Vec<String> {
+            theme
+                .get(key)
+                .map(|value| {
+                    value
+                        .split(',')
+                        .map(str::trim)
+                        .filter(|v| !v.is_empty())
+                        .map(String::from)
+                        .collect()
+                })
+                .unwrap_or_default()
+        };
+
+        let directories = list("Directories")
+            .into_iter()
+            .chain(list("ScaledDirectories"))
+            .filter_map(|path| {
+                let section = sections.get(path.as_str())?;
+                let number = |key: &str| section.get(key).and_then(|v| v.parse::<u32>().ok());
+                let size = number("Size")?;
+                Some(Directory {
+                    size,
+                    scale: number("Scale").unwrap_or(1),
+                    kind: match section.get("Type").copied() {
+                        Some("Fixed") => DirectoryType::Fixed,
+                        Some("Scalable") => DirectoryType::Scalable,
+                        _ => DirectoryType::Threshold,
+                    },
+                    min_size: number("MinSize").unwrap_or(size),
+                    max_size: number("MaxSize").unwrap_or(size),
+                    threshold: number("Threshold").unwrap_or(2),
+                    path,
+                })
+            })
+            .collect();
+
+        Theme {
+            name: name.to_string(),
+            parents: list("Inherits"),
+            directories,
+        }
+    }
+
+    fn lookup(&self, icon: &str, size: u32) -> Option<PathBuf> {
+        let bases = &base_directories();
+        let candidates = || {
+            self.directories.iter().flat_map(move |dir| {
+                bases.iter().flat_map(move |base| {
+                    EXTENSIONS.iter().map(move |ext| {
+                        (
+                            dir,
+                            base.join(&self.name)
+                                .join(&dir.path)
+                                .join(format!("{}.{}", icon, ext)),
+                        )
+                    })
+                })
+            })
+        };
+
+        let exact = candidates().find(|(dir, path)| dir.matches_size(size) && path.is_file());
+        if let Some((_, path)) = exact {
+            return Some(path);
+        }
+
+        candidates()
+            .filter(|(_, path)| path.is_file())
+            .min_by_key(|(dir, _)| dir.size_distance(size))
+            .map(|(_, path)| path)
+    }
+}
+
+/// The directories icon themes are searched in, in order of priority
+fn base_directories() -> Vec<PathBuf> {
+    let mut dirs = vec![];
+
+    if let Some(home) = std::env::var_os("HOME") {
+        dirs.push(Path::new(&home).join(".icons"));
+    }
+
+    match std::env::var_os("XDG_DATA_HOME") {
+        Some(data_home) => dirs.push(Path::new(&data_home).join("icons")),
+        None => {
+            if let Some(home) = std::env::var_os("HOME") {
+                dirs.push(Path::new(&home).join(".local/share/icons"));
+            }
+        }
+    }
+
+    let data_dirs = std::env::var("XDG_DATA_DIRS")
+        .ok()
+        .filter(|dirs| !dirs.is_empty())
+        .unwrap_or_else(|| "/usr/local/share:/usr/share".to_string());
+    dirs.extend(
+        data_dirs
+            .split(':')
+            .filter(|dir| !dir.is_empty())
+            .map(|dir| Path::new(dir).join("icons")),
+    );
+
+    dirs.push(PathBuf::from("/usr/share/pixmaps"));
+
+    dirs
+}
+
+fn find_in_theme(icon: &str, size: u32, theme: &str, visited: &mut Vec<String>) -> Option<PathBuf> {
+    if visited.iter().any(|t| t == theme) {
+        return None;
+    }
+    visited.push(theme.to_string());
+
+    let theme = Theme::load(theme)?;
+
+    if let Some(path) = theme.lookup(icon, size) {
+        return Some(path);
+    }
+
+    theme
+        .parents
+        .iter()
+        .find_map(|parent| find_in_theme(icon, size, parent, visited))
+}
+
+fn find_fallback(icon: &str) -> Option<PathBuf> {
+    base_directories()
+        .iter()
+        .flat_map(|base| {
+            EXTENSIONS
+                .iter()
+                .map(move |ext| base.join(format!("{}.{}", icon, ext)))
+        })
+        .find(|path| path.is_file())
+}
+
+/// The value of `key` in a GTK `settings.ini` or `.gtkrc-2.0`
+fn gtk_setting(content: &str, key: &str) -> Option<String> {
+    content.lines().find_map(|line| {
+        let idx = line.find('=')?;
+        if line[..idx].trim() != key {
+            return None;
+        }
+        let value = line[idx + 1..].trim().trim_matches('"');
+        Some(value.to_string()).filter(|value| !value.is_empty())
+    })
+}
+
+/// The icon theme the user configured for GTK, which desktops and settings
+/// daemons write for other toolkits to follow
+fn user_theme() -> Option<String> {
+    let home = std::env::var_os("HOME").map(PathBuf::from);
+    let config_home = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| home.as_ref().map(|home| home.join(".config")));
+    let config_dirs = std::env::var("XDG_CONFIG_DIRS")
+        .ok()
+        .filter(|dirs| !dirs.is_empty())
+        .unwrap_or_else(|| "/etc/xdg".to_string());
+
+    let settings = config_home
+        .into_iter()
+        .chain(config_dirs.split(':').map(PathBuf::from))
+        .flat_map(|dir| {
+            vec![
+                dir.join("gtk-3.0/settings.ini"),
+                dir.join("gtk-4.0/settings.ini"),
+            ]
+        })
+        .chain(home.map(|home| home.join(".gtkrc-2.0")));
+
+    settings
+        .filter_map(|path| std::fs::read_to_string(path).ok())
+        .find_map(|content| gtk_setting(&content, "gtk-icon-theme-name"))
+}
+
+/// Find the file of the icon `icon` closest to `size` in `theme`
+///
+/// Without a `theme` the one configured for GTK is used. The lookup goes
+/// through the parents of the theme, then `hicolor` and at last the icons that
+/// are not part of any theme. Only icons that were found are remembered, so
+/// one installed later is found on the next lookup.
+pub fn lookup(icon: &str, size: u32, theme: Option<&str>) -> Option<PathBuf> {
+    let key = (icon.to_string(), size, theme.map(String::from));
+    if let Some(path) = ICONS.with(|icons| icons.borrow().get(&key).cloned()) {
+        return Some(path);
+    }
+
+    let theme = theme
+        .map(String::from)
+        .or_else(|| USER_THEME.with(Clone::clone));
+
+    let mut visited = vec![];
+    let path = theme
+        .and_then(|theme| find_in_theme(icon, size, &theme, &mut visited))
+        .or_else(|| find_in_theme(icon, size, FALLBACK_THEME, &mut visited))
+        .or_else(|| find_fallback(icon));
+
+    if let Some(path) = &path {
+        ICONS.with(|icons| icons.borrow_mut().insert(key, path.clone()));
+    }
+    path
+}
@@ -0,0 +1,592 @@
+use anyhow::Context;
+use futures::stream::{self, BoxStream, StreamExt};
+use serde_json::Value;
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, BufReader};
+
+use crate::check::Checker;
+use crate::message::Message;
+
+/// Ends a document of the text formats besides JSON when on a line of its own
+const DOCUMENT_SEPARATOR: &str = "---";
+
+/// The longest MessagePack or CBOR message taken, in bytes
+const MAX_FRAME_LENGTH: u32 = 16 * 1024 * 1024;
+
+/// How the messages on the input are encoded
+///
+/// JSON takes consecutive values whatever the whitespace between them, YAML,
+/// TOML and RON documents are separated by a `---` line and MessagePack and
+/// CBOR messages are each preceded by their length as a big-endian `u32`.
+///
+/// A TOML document can't be an array, so objects are sent as its `[[objects]]`
+/// tables, like in the `{"objects": [...]}` message.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InputFormat {
+    Json,
+    Yaml,
+    Toml,
+    Ron,
+    Msgpack,
+    Cbor,
+}
+
+impl InputFormat {
+    pub const NAMES: &'static [&'static str] = &["json", "yaml", "toml", "ron", "msgpack", "cbor"];
+}
+
+impl std::str::FromStr for InputFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(input: &str) -> anyhow::Result<InputFormat> {
+        Ok(match input {
+            "json" => InputFormat::Json,
+            "yaml" => InputFormat::Yaml,
+            "toml" => InputFormat::Toml,
+            "ron" => InputFormat::Ron,
+            "msgpack" => InputFormat::Msgpack,
+            "cbor" => InputFormat::Cbor,
+            _ => {
+                return Err(anyhow::anyhow!(
+                    "{} needs to be one of: {}",
+                    input,
+                    InputFormat::NAMES.join(", ")
+                ))
+            }
+        })
+    }
+}
+
+impl std::fmt::Display for InputFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let index = match self {
+            InputFormat::Json => 0,
+            InputFormat::Yaml => 1,
+            InputFormat::Toml => 2,
+            InputFormat::Ron => 3,
+            InputFormat::Msgpack => 4,
+            InputFormat::Cbor => 5,
+        };
+        f.write_str(InputFormat::NAMES[index])
+    }
+}
+
+/// A message that could not be understood, reading goes on with the next one
+/// unless the length of a MessagePack or CBOR message is too large to trust
+#[derive(Debug)]
+pub struct ParseError(pub InputFormat);
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Could not parse {} input", self.0)
+    }
+}
+
+fn parse(format: InputFormat, input: &[u8], checker: &Checker) -> anyhow::Result<Message> {
+    // Unless strict, a message is read straight from the input and only read
+    // again as a value to tell what's wrong with it
+    if !checker.is_strict() {
+        if let Ok(message) = decode(format, input) {
+            return Ok(message);
+        }
+    }
+
+    decode::<Value>(format, input)
+        .and_then(|value| checker.decode(value))
+        .context(ParseError(format))
+}
+
+fn decode<T: serde::de::DeserializeOwned>(format: InputFormat, input: &[u8]) -> anyhow::Result<T> {
+    Ok(match format {
+        InputFormat::Json => serde_json::from_slice(input)?,
+        InputFormat::Yaml => serde_yaml::from_slice(input)?,
+        InputFormat::Toml => toml::from_slice(input)?,
+        InputFormat::Ron => ron::de::from_bytes(input)?,
+        InputFormat::Msgpack => rmp_serde::from_read_ref(input)?,
+        InputFormat::Cbor => serde_cbor::from_slice(input)?,
+    })
+}
+
+/// The first JSON value of `input` as a message and the offset it ends at,
+/// the syntax error in it, or `None` if it needs more input
+fn read_json(
+    input: &[u8],
+    checker: &Checker,
+) -> Option<Result<(anyhow::Result<Message>, usize), serde_json::Error>> {
+    if !checker.is_strict() {
+        let mut messages = serde_json::Deserializer::from_slice(input).into_iter::<Message>();
+        match messages.next()? {
+            Ok(message) => return Some(Ok((Ok(message), messages.byte_offset()))),
+            // Read again as a value below, to find its end and tell what's
+            // wrong with it
+            Err(e) if e.is_data() => {}
+            Err(e) if e.is_eof() => return None,
+            Err(e) => return Some(Err(e)),
+        }
+    }
+
+    let mut values = serde_json::Deserializer::from_slice(input).into_iter::<Value>();
+    match values.next()? {
+        Ok(value) => Some(Ok((checker.decode(value), values.byte_offset()))),
+        Err(e) if e.is_eof() => None,
+        Err(e) => Some(Err(e)),
+    }
+}
+
+/// Where reading JSON values is at
+struct JsonInput<R> {
+    reader: R,
+    checker: Arc<Checker>,
+    buffer: Vec<u8>,
+    /// After a syntax error lines are dropped up to one starting with `[` or
+    /// `{`, so a broken pretty-printed value only gives one error
+    skipping: bool,
+    eof: bool,
+    scan: Scan,
+}
+
+/// How far the buffer was looked through for the end of the first value, so
+/// it is only parsed once that may be complete instead of after every read
+#[derive(Default)]
+struct Scan {
+    offset: usize,
+    depth: usize,
+    in_string: bool,
+    escaped: bool,
+    /// The value is no array or object, so its end is left to the parser
+    scalar: bool,
+}
+
+impl Scan {
+    /// Whether `buffer` may hold a complete value, looking only at what was
+    /// added since the last call
+    fn may_be_complete(&mut self, buffer: &[u8]) -> bool {
+        if self.scalar {
+            return true;
+        }
+
+        for (offset, &byte) in buffer.iter().enumerate().skip(self.offset) {
+            if self.in_string {
+                match byte {
+                    _ if self.escaped => self.escaped = false,
+                    b'\\' => self.escaped = true,
+                    b'"' => self.in_string = false,
+                    _ => {}
+                }
+                continue;
+            }
+
+            match byte {
+                b'[' | b'{' => self.depth += 1,
+                b']' | b'}' if self.depth > 0 => {
+                    self.depth -= 1;
+                    if self.depth == 0 {
+                        self.offset = offset + 1;
+                        return true;
+                    }
+                }
+                b'"' if self.depth > 0 => self.in_string = true,
+                _ if self.depth == 0 && !byte.is_ascii_whitespace() => {
+                    self.scalar = true;
+                    return true;
+                }
+                _ => {}
+            }
+        }
+
+        self.offset = buffer.len();
+        false
+    }
+}
+
+impl<R: AsyncRead + Unpin> JsonInput<R> {
+    async fn next(&mut self) -> Option<anyhow::Result<Message>> {
+        loop {
+            if self.skipping {
+                let restart = self
+                    .buffer
+                    .windows(2)
+                    .position(|pair| pair[0] == b'\n' && (pair[1] == b'[' || pair[1] == b'{'));
+
+                match restart {
+                    Some(position) => {
+                        self.buffer.drain(..=position);
+                        self.skipping = false;
+                        self.scan = Scan::default();
+                    }
+                    // Keep the last newline, the line after it may still
+                    // turn out to start a value
+                    None => match self.buffer.iter().rposition(|&byte| byte == b'\n') {
+                        Some(position) => {
+                            self.buffer.drain(..position);
+                        }
+                        None => self.buffer.clear(),
+                    },
+                }
+            }
+
+            if !self.skipping && (self.eof || self.scan.may_be_complete(&self.buffer)) {
+                match read_json(&self.buffer, &self.checker) {
+                    Some(Ok((message, end))) => {
+                        self.buffer.drain(..end);
+                        self.scan = Scan::default();
+                        return Some(message.context(ParseError(InputFormat::Json)));
+                    }
+                    Some(Err(e)) => {
+                        // Drop the start of the broken value so it isn't
+                        // taken for the next one
+                        let start = self
+                            .buffer
+                            .iter()
+                            .position(|byte| !byte.is_ascii_whitespace())
+                            .unwrap_or(0);
+                        self.buffer.drain(..=start);
+                        self.skipping = true;
+                        return Some(Err(e).context(ParseError(InputFormat::Json)));
+                    }
+                    // Needs more input to be complete
+                    None => {}
+                }
+            }
+
+            if self.eof {
+                if self.skipping || self.buffer.iter().all(u8::is_ascii_whitespace) {
+                    return None;
+                }
+
+                self.buffer.clear();
+                return Some(
+                    Err(anyhow::anyhow!("The input ended within a value"))
+                        .context(ParseError(InputFormat::Json)),
+                );
+            }
+
+            let mut chunk = [0; 4096];
+            match self.reader.read(&mut chunk).await {
+                Ok(0) => self.eof = true,
+                Ok(length) => self.buffer.extend_from_slice(&chunk[..length]),
+                Err(e) => {
+                    self.eof = true;
+                    return Some(Err(e.into()));
+                }
+            }
+        }
+    }
+}
+
+fn check_framing(format: InputFormat, nul_delimited: bool) -> anyhow::Result<()> {
+    match format {
+        InputFormat::Msgpack | InputFormat::Cbor if nul_delimited => Err(anyhow::anyhow!(
+            "{} messages are preceded by their length and can't be delimited by NUL",
+            format
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// The messages read from `reader`
+///
+/// With `nul_delimited` the messages of the text formats are instead
+/// separated by NUL bytes. With `strict` messages with properties viereck
+/// doesn't know are rejected instead of having those ignored.
+pub fn messages<R>(
+    reader: R,
+    format: InputFormat,
+    nul_delimited: bool,
+    strict: bool,
+) -> anyhow::Result<BoxStream<'static, anyhow::Result<Message>>>
+where
+    R: AsyncRead + Unpin + Send + 'static,
+{
+    check_framing(format, nul_delimited)?;
+
+    let checker = Arc::new(Checker::new(strict));
+
+    if nul_delimited {
+        return Ok(BufReader::new(reader)
+            .split(0)
+            .filter_map(move |message| {
+                futures::future::ready(match message {
+                    Ok(message) if message.iter().all(u8::is_ascii_whitespace) => None,
+                    Ok(message) => Some(parse(format, &message, &checker)),
+                    Err(e) => Some(Err(e.into())),
+                })
+            })
+            .boxed());
+    }
+
+    Ok(match format {
+        InputFormat::Json => {
+            let input = JsonInput {
+                reader,
+                checker,
+                buffer: vec![],
+                skipping: false,
+                eof: false,
+                scan: Scan::default(),
+            };
+
+            stream::unfold(input, |mut input| async move {
+                let message = input.next().await?;
+                Some((message, input))
+            })
+            .boxed()
+        }
+        InputFormat::Yaml | InputFormat::Toml | InputFormat::Ron => {
+            stream::unfold(Some(BufReader::new(reader).lines()), move |lines| {
+                let checker = checker.clone();
+                async move {
+                    let mut lines = lines?;
+                    let mut document = String::new();
+
+                    while let Some(line) = lines.next().await {
+                        let line = match line {
+                            Ok(line) => line,
+                            Err(e) => return Some((Err(e.into()), Some(lines))),
+                        };
+
+                        if line.trim_end() != DOCUMENT_SEPARATOR {
+                            document.push_str(&line);
+                            document.push('\n');
+                        } else if !document.trim().is_empty() {
+                            return Some((
+                                parse(format, document.as_bytes(), &checker),
+                                Some(lines),
+                            ));
+                        }
+                    }
+
+                    if document.trim().is_empty() {
+                        None
+                    } else {
+                        Some((parse(format, document.as_bytes(), &checker), None))
+                    }
+                }
+            })
+            .boxed()
+        }
+        InputFormat::Msgpack | InputFormat::Cbor => stream::unfold(Some(reader), move |reader| {
+            let checker = checker.clone();
+            async move {
+                let mut reader = reader?;
+
+                let length = match reader.read_u32().await {
+                    Ok(length) => length,
+                    Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return None,
+                    Err(e) => return Some((Err(e.into()), None)),
+                };
+
+                // The stream can't be resynced, as any byte could start the next length
+                if length > MAX_FRAME_LENGTH {
+                    return Some((
+                        Err(anyhow::anyhow!(
+                            "The message is {} bytes long, longer than the maximum of {}",
+                            length,
+                            MAX_FRAME_LENGTH
+                        ))
+                        .context(ParseError(format)),
+                        None,
+                    ));
+                }
+
+                let mut frame = vec![0; length as usize];
+                if let Err(e) = reader.read_exact(&mut frame).await {
+                    return Some((Err(e.into()), None));
+                }
+
+                Some((parse(format, &frame, &checker), Some(reader)))
+            }
+        })
+        .boxed(),
+    })
+}
+
+/// Waits after a failed `accept`, doubling up to `MAX_ACCEPT_BACKOFF` while
+/// it keeps failing, e.g. as long as there are no file descriptors left
+const MIN_ACCEPT_BACKOFF: std::time::Duration = std::time::Duration::from_millis(10);
+const MAX_ACCEPT_BACKOFF: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// The socket created by `listen`, removed again when this is dropped
+pub struct SocketFile(String);
+
+impl Drop for SocketFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+/// The messages of every client connecting to a unix socket at `path`
+///
+/// A socket left at `path` by an earlier run is replaced, unless something
+/// still listens on it.
+pub fn listen(
+    path: &str,
+    format: InputFormat,
+    nul_delimited: bool,
+    strict: bool,
+) -> anyhow::Result<(BoxStream<'static, anyhow::Result<Message>>, SocketFile)> {
+    use std::os::unix::fs::FileTypeExt;
+
+    // Fails early instead of with the first client
+    check_framing(format, nul_delimited)?;
+
+    let is_socket = std::fs::metadata(path)
+        .map(|metadata| metadata.file_type().is_socket())
+        .unwrap_or(false);
+    if is_socket {
+        match std::os::unix::net::UnixStream::connect(path) {
+            Ok(_) => return Err(anyhow::anyhow!("{} is already in use", path)),
+            Err(e) if e.kind() == std::io::ErrorKind::ConnectionRefused => {
+                std::fs::remove_file(path)
+                    .with_context(|| format!("Could not remove the old socket {}", path))?;
+            }
+            // Left for `bind` to report
+            Err(_) => {}
+        }
+    }
+
+    let mut listener = tokio::net::UnixListener::bind(path)
+        .with_context(|| format!("Could not listen on {}", path))?;
+    let socket = SocketFile(path.to_string());
+
+    let (sender, receiver) = tokio::sync::mpsc::channel(16);
+
+    tokio::spawn(async move {
+        let mut backoff = MIN_ACCEPT_BACKOFF;
+        loop {
+            let mut sender = sender.clone();
+            let client = match listener.accept().await {
+                Ok((client, _)) => {
+                    backoff = MIN_ACCEPT_BACKOFF;
+                    client
+                }
+                Err(e) => {
+                    if sender.send(Err(e.into())).await.is_err() {
+                        return;
+                    }
+                    tokio::time::delay_for(backoff).await;
+                    backoff = (backoff * 2).min(MAX_ACCEPT_BACKOFF);
+                    continue;
+                }
+            };
+
+            tokio::spawn(async move {
+                let mut messages = match messages(client, format, nul_delimited, strict) {
+                    Ok(messages) => messages,
+                    Err(_) => return,
+                };
+
+                while let Some(message) = messages.next().await {
+                    if sender.send(message).await.is_err() {
+                        return;
+                    }
+                }
+            });
+        }
+    });
+
+    Ok((receiver.boxed(), socket))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Gives one of its chunks on every read
+    struct Chunks(std::collections::VecDeque<&'static [u8]>);
+
+    impl AsyncRead for Chunks {
+        fn poll_read(
+            mut self: std::pin::Pin<&mut Self>,
+            _: &mut std::task::Context<'_>,
+            buffer: &mut [u8],
+        ) -> std::task::Poll<std::io::Result<usize>> {
+            let chunk = self.0.pop_front().unwrap_or_default();
+            buffer[..chunk.len()].copy_from_slice(chunk);
+            std::task::Poll::Ready(Ok(chunk.len()))
+        }
+    }
+
+    /// The messages read from `chunks` as JSON again, `error` for those that
+    /// could not be parsed
+    fn read(chunks: &[&'static [u8]], nul_delimited: bool) -> Vec<String> {
+        let reader = Chunks(chunks.iter().copied().collect());
+        let messages = messages(reader, InputFormat::Json, nul_delimited, false).unwrap();
+        tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(messages.collect::<Vec<_>>())
+            .into_iter()
+            .map(|message| match message {
+                Ok(message) => serde_json::to_string(&message).unwrap(),
+                Err(_) => "error".to_string(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn value_split_across_reads() {
+        assert_eq!(
+            read(
+                &[
+                    b"{\"render\": \"a\", \"da",
+                    b"ta\": {\"x\": [1,",
+                    b" 2]}}\n{\"cmd\"",
+                    b": \"show\"}"
+                ],
+                false
+            ),
+            vec![
+                "{\"render\":\"a\",\"data\":{\"x\":[1,2]}}",
+                "{\"cmd\":\"show\"}"
+            ]
+        );
+    }
+
+    #[test]
+    fn values_in_one_read() {
+        assert_eq!(
+            read(&[b"{\"cmd\":\"hide\"}{\"cmd\":\"show\"} []\n[]"], false),
+            vec!["{\"cmd\":\"hide\"}", "{\"cmd\":\"show\"}", "[]", "[]"]
+        );
+    }
+
+    #[test]
+    fn brackets_and_quotes_in_strings() {
+        assert_eq!(
+            read(
+                &[
+                    br#"{"render": "]}\"#,
+                    br#""{[", "data": {"a": "\\"}}"#,
+                    br#"{"cmd": "hide"}"#
+                ],
+                false
+            ),
+            vec![
+                r#"{"render":"]}\"{[","data":{"a":"\\"}}"#,
+                r#"{"cmd":"hide"}"#
+            ]
+        );
+    }
+
+    #[test]
+    fn one_error_for_broken_value() {
+        let input: &[u8] = b"[\n  {\"cmd\": \"hide\",,\n  }\n]\n{\n  \"cmd\": \"show\"\n}\n";
+        assert_eq!(read(&[input], false), vec!["error", "{\"cmd\":\"show\"}"]);
+    }
+
+    #[test]
+    fn input_ending_within_value() {
+        assert_eq!(read(&[b"[] [{\"cmd\""], false), vec!["[]", "error"]);
+    }
+
+    #[test]
+    fn nul_delimited() {
+        assert_eq!(
+            read(
+                &[b"{\"cmd\":\n\"hi", b"de\"}\0\0 \0[]\0{\"cmd\"}\0[]"],
+                true
+            ),
+            vec!["{\"cmd\":\"hide\"}", "[]", "error", "[]"]
+        );
+    }
+}
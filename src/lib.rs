@@ -1,4 +1,12 @@
+pub mod animation;
+pub mod check;
+pub mod color;
+pub mod icon;
+pub mod message;
 pub mod object;
-#[path = "bin/common/style.rs"]
+pub mod picture;
 pub mod style;
+pub mod stylesheet;
+pub mod template;
+pub mod theme;
 pub use object::Object;
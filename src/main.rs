@@ -1,14 +1,26 @@
 use futures::stream::StreamExt as FStreamExt;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
 use structopt::StructOpt;
-use tokio::io::AsyncBufReadExt;
+
+use viereck::{
+    animation, check, color, message, object, picture, style, stylesheet, template, theme,
+};
 
 mod draw;
-pub mod object;
+mod input;
+mod timer;
 mod window;
 
 #[derive(Debug, StructOpt)]
-#[structopt(name = "viereck", about = "Viereck is a versatile drawing program")]
+#[structopt(
+    name = "viereck",
+    about = "Viereck is a versatile drawing program",
+    setting = structopt::clap::AppSettings::SubcommandsNegateReqs
+)]
 struct CmdOptions {
+    #[structopt(subcommand)]
+    command: Option<Subcommand>,
     /// X position
     #[structopt(short, long, default_value = "0")]
     x: i16,
@@ -16,43 +28,240 @@ struct CmdOptions {
     #[structopt(short, long, default_value = "0")]
     y: i16,
     /// Width position
-    #[structopt(short, long)]
-    width: u16,
+    #[structopt(short, long, required = true)]
+    width: Option<u16>,
     /// Height position
-    #[structopt(short, long)]
-    height: u16,
+    #[structopt(short, long, required = true)]
+    height: Option<u16>,
+    /// A TOML file of named colors, which objects can refer to as `$name`
+    #[structopt(long)]
+    theme: Option<String>,
+    /// A JSON file of style rules, which objects are selected from by type,
+    /// class and id
+    #[structopt(long)]
+    stylesheet: Option<String>,
+    /// Font of texts that neither they nor a container around them set
+    #[structopt(long)]
+    font: Option<String>,
+    /// Font size of texts that neither they nor a container around them set
+    #[structopt(long)]
+    font_size: Option<f64>,
+    /// Color of texts that neither they nor a container around them set
+    #[structopt(long)]
+    color: Option<color::Color>,
+    /// How the messages on stdin are encoded
+    ///
+    /// JSON takes consecutive values however they are spread over lines, YAML,
+    /// TOML and RON documents are separated by a `---` line and MessagePack
+    /// and CBOR messages are each preceded by their length as a big-endian 32
+    /// bit number. TOML documents give their objects as `[[objects]]` tables
+    #[structopt(long, default_value = "json", possible_values = input::InputFormat::NAMES)]
+    input_format: input::InputFormat,
+    /// Separate the messages on stdin by NUL bytes, for text formats only
+    #[structopt(long)]
+    nul_delimited: bool,
+    /// Reject messages with properties viereck doesn't know, instead of
+    /// ignoring those properties
+    #[structopt(long)]
+    strict: bool,
+    /// Also take messages from any number of clients connecting to a unix
+    /// socket at this path, e.g. with `viereck-msg`
+    #[structopt(long)]
+    listen: Option<String>,
+    /// Hide the window this many seconds after the last update, or exit if
+    /// stdin is closed by then and `--persist` is not given
+    #[structopt(long)]
+    timeout: Option<f64>,
+    /// Exit once stdin is closed, or after the timeout if one is running,
+    /// the default unless `--persist` is given
+    #[structopt(long, conflicts_with = "persist")]
+    exit_on_eof: bool,
+    /// Keep showing the last update once stdin is closed, even after the
+    /// timeout, or exit this many seconds after stdin is closed, like `-p` of
+    /// dzen2
+    #[structopt(long)]
+    persist: Option<Option<f64>>,
+    /// Draw at most this many frames per second, input arriving faster is
+    /// coalesced and only the latest objects are drawn
+    #[structopt(long, default_value = "60")]
+    max_fps: f64,
+}
+
+#[derive(Debug, StructOpt)]
+enum Subcommand {
+    /// Print a JSON Schema of the messages viereck takes, for editors to
+    /// validate and complete them
+    Schema,
+    /// Check the messages on stdin without drawing them, reporting where each
+    /// problem is as in `--strict`
+    Check,
+}
+
+/// Reports the problems of every message on stdin, failing if there were any
+async fn check_messages(format: input::InputFormat, nul_delimited: bool) -> anyhow::Result<()> {
+    let mut messages = input::messages(tokio::io::stdin(), format, nul_delimited, true)?;
+
+    let mut count = 0;
+    let mut failed = 0;
+    while let Some(message) = messages.next().await {
+        count += 1;
+        if let Err(e) = message {
+            failed += 1;
+            eprintln!("Message {}: {}", count, e);
+            e.chain().skip(1).for_each(|c| eprintln!("because: {}", c));
+        }
+    }
+
+    anyhow::ensure!(
+        failed == 0,
+        "{} of {} messages have problems",
+        failed,
+        count
+    );
+    Ok(())
+}
+
+/// The root objects with their slots filled by what was sent for them
+fn with_slots(
+    root_objects: &[object::Object],
+    slots: &HashMap<String, Vec<object::Object>>,
+) -> Vec<object::Object> {
+    let mut objects = root_objects.to_vec();
+    for object in &mut objects {
+        object.fill_slots(slots);
+    }
+    objects
+}
+
+fn seconds(seconds: f64) -> Duration {
+    Duration::from_secs_f64(seconds.max(0.))
 }
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
+    use anyhow::Context;
+
     let opt = CmdOptions::from_args();
 
-    let mut win = window::Window::new(opt.x, opt.y, opt.width, opt.height)?;
+    match opt.command {
+        Some(Subcommand::Schema) => {
+            let schema = schemars::schema_for!(message::Message);
+            println!("{}", serde_json::to_string_pretty(&schema)?);
+            return Ok(());
+        }
+        Some(Subcommand::Check) => {
+            return check_messages(opt.input_format, opt.nul_delimited).await
+        }
+        None => {}
+    }
+
+    let width = opt.width.context("--width is required")?;
+    let height = opt.height.context("--height is required")?;
+    let mut win = window::Window::new(opt.x, opt.y, width, height)?;
+
+    if let Some(path) = &opt.theme {
+        win.set_theme(theme::Theme::load(path)?);
+    }
+
+    if let Some(path) = &opt.stylesheet {
+        win.set_stylesheet(stylesheet::Stylesheet::load(path)?);
+    }
+
+    let defaults = object::Inherited::default();
+    win.set_text_defaults(object::Inherited {
+        font: opt.font.or(defaults.font),
+        font_size: opt.font_size.or(defaults.font_size),
+        color: opt.color.or(defaults.color),
+    });
 
     let mut root_objs = vec![];
+    let mut slots = HashMap::new();
+    let mut templates = template::Templates::default();
+    let checker = check::Checker::new(opt.strict);
 
     enum Events {
         Window(window::WindowEvent),
-        Input(Vec<object::Object>),
+        Input(message::Message),
+        InputClosed,
+        Draw,
+        Tick,
+        Timeout,
+        PersistOver,
     }
 
+    anyhow::ensure!(opt.max_fps > 0., "--max-fps needs to be more than 0");
+    let frame_interval = seconds(1. / opt.max_fps);
+    // Drawing and animating wait for this much time since the last frame
+    let mut last_frame: Option<Instant> = None;
+    let next_frame_at = move |last_frame: Option<Instant>| {
+        last_frame.map_or_else(Instant::now, |last_frame| last_frame + frame_interval)
+    };
+    let mut draw_pending = false;
+
+    let (draw_timer, draw_timer_handle) = timer::Timer::new();
+    let draw_events = draw_timer.map(|_| Ok(Events::Draw));
+
+    let (frame_timer, frame_timer_handle) = timer::Timer::new();
+    let tick_events = frame_timer.map(|_| Ok(Events::Tick));
+
+    let (hide_timer, hide_timer_handle) = timer::Timer::new();
+    let timeout_events = hide_timer.map(|_| Ok(Events::Timeout));
+    let timeout = opt.timeout.map(seconds);
+    // When the window is hidden next, and whether it was hidden by that
+    let mut hide_at = None;
+    let mut timed_out = false;
+    let mut input_closed = false;
+    let exit_on_eof = opt.exit_on_eof || opt.persist.is_none();
+
+    let (persist_timer, persist_timer_handle) = timer::Timer::new();
+    let persist_events = persist_timer.map(|_| Ok(Events::PersistOver));
+
     let windows_events = win.event_stream()?.map(|e| e.map(Events::Window));
 
-    let input_events = tokio::io::BufReader::new(tokio::io::stdin())
-        .lines()
-        .map(|l| match l {
-            Ok(line) => Ok(serde_json::from_str(&line).map(Events::Input)?),
-            Err(e) => Err(e.into()),
-        });
+    let input_events = input::messages(
+        tokio::io::stdin(),
+        opt.input_format,
+        opt.nul_delimited,
+        opt.strict,
+    )?
+    .map(|message| message.map(Events::Input))
+    .chain(futures::stream::once(futures::future::ready(Ok(
+        Events::InputClosed,
+    ))));
+    // The socket is removed once this is dropped on exit
+    let (input_events, _socket) = match &opt.listen {
+        Some(path) => {
+            let (messages, socket) =
+                input::listen(path, opt.input_format, opt.nul_delimited, opt.strict)?;
+            let input_events = futures::stream::select(
+                input_events,
+                messages.map(|message| message.map(Events::Input)),
+            );
+            (input_events.boxed(), Some(socket))
+        }
+        None => (input_events.boxed(), None),
+    };
 
-    let mut events = futures::stream::select(windows_events, input_events);
+    let mut events = Box::pin(futures::stream::select(
+        futures::stream::select(windows_events, input_events),
+        futures::stream::select(
+            draw_events,
+            futures::stream::select(
+                tick_events,
+                futures::stream::select(timeout_events, persist_events),
+            ),
+        ),
+    ));
 
     while let Some(ev) = events.next().await {
+        // Set to the timeout of a message that changed the objects
+        let mut updated = None;
+
         match ev {
             Ok(Events::Window(ev)) => {
                 match ev {
                     window::WindowEvent::Draw => {
-                        win.draw(root_objs.clone())?;
+                        draw_pending = true;
                     }
                     _ => {
                         // Unknown event? Not cared
@@ -60,13 +269,103 @@ async fn main() -> anyhow::Result<()> {
                     }
                 }
             }
-            Ok(Events::Input(new_objs)) => {
+            Ok(Events::Input(message::Message::Objects(new_objs))) => {
                 root_objs = new_objs;
-                win.draw(root_objs.clone())?;
+                draw_pending = true;
+                updated = Some(None);
+            }
+            Ok(Events::Input(message::Message::Update(update))) => {
+                match update.slot {
+                    Some(slot) => {
+                        slots.insert(slot, update.objects);
+                    }
+                    None => root_objs = update.objects,
+                }
+                draw_pending = true;
+                updated = Some(update.timeout);
+            }
+            Ok(Events::Input(message::Message::Theme(theme))) => {
+                win.set_theme(theme);
+                draw_pending = true;
+            }
+            Ok(Events::Input(message::Message::Stylesheet(stylesheet))) => {
+                win.set_stylesheet(stylesheet);
+                draw_pending = true;
+            }
+            Ok(Events::Input(message::Message::Template(template))) => {
+                templates.insert(template);
+            }
+            Ok(Events::Input(message::Message::Render(render))) => {
+                match templates.render(&render, &checker) {
+                    Ok(new_objs) => {
+                        match render.slot {
+                            Some(slot) => {
+                                slots.insert(slot, new_objs);
+                            }
+                            None => root_objs = new_objs,
+                        }
+                        draw_pending = true;
+                        updated = Some(render.timeout);
+                    }
+                    Err(e) => {
+                        eprintln!("Could not render: {}", e);
+                        e.chain().skip(1).for_each(|c| eprintln!("because: {}", c));
+                    }
+                }
+            }
+            Ok(Events::Input(message::Message::Command(command))) => match command {
+                message::Command::Hide => win.hide()?,
+                message::Command::Show => win.show()?,
+                message::Command::Move { x, y } => win.move_to(x, y)?,
+                message::Command::Resize { width, height } => {
+                    win.resize(width, height)?;
+                    draw_pending = true;
+                }
+                message::Command::Raise => win.raise()?,
+                message::Command::Exit => break,
+            },
+            Ok(Events::InputClosed) => {
+                input_closed = true;
+                if exit_on_eof && hide_at.is_none() {
+                    break;
+                }
+                match opt.persist {
+                    Some(Some(persist)) => {
+                        persist_timer_handle.set(Some(Instant::now() + seconds(persist)))
+                    }
+                    Some(None) => {
+                        hide_at = None;
+                        hide_timer_handle.set(None);
+                    }
+                    None => {}
+                }
+            }
+            Ok(Events::Timeout) => {
+                hide_at = None;
+                if input_closed && exit_on_eof {
+                    break;
+                }
+                if !(input_closed && opt.persist == Some(None)) {
+                    win.hide()?;
+                    timed_out = true;
+                }
+            }
+            Ok(Events::PersistOver) => break,
+            Ok(Events::Draw) => {
+                if let Err(e) = win.draw(with_slots(&root_objs, &slots)) {
+                    eprintln!("Could not draw: {}", e);
+                    e.chain().skip(1).for_each(|c| eprintln!("because: {}", c));
+                }
+                last_frame = Some(Instant::now());
+                draw_pending = false;
+            }
+            Ok(Events::Tick) => {
+                win.tick()?;
+                last_frame = Some(Instant::now());
             }
             Err(e) => {
-                if let Some(serde_json::error::Error { .. }) = e.downcast_ref() {
-                    eprintln!("Could not parse input: {}", e);
+                if let Some(input::ParseError(_)) = e.downcast_ref() {
+                    eprintln!("{}", e);
                     e.chain().skip(1).for_each(|c| eprintln!("because: {}", c));
                     continue;
                 }
@@ -75,6 +374,26 @@ async fn main() -> anyhow::Result<()> {
                 e.chain().skip(1).for_each(|c| eprintln!("because: {}", c));
             }
         }
+
+        if let Some(message_timeout) = updated {
+            if timed_out {
+                win.show()?;
+                timed_out = false;
+            }
+            hide_at = message_timeout
+                .map(seconds)
+                .or(timeout)
+                .map(|timeout| Instant::now() + timeout);
+            hide_timer_handle.set(hide_at);
+        }
+
+        if draw_pending {
+            draw_timer_handle.set(Some(next_frame_at(last_frame)));
+        }
+        frame_timer_handle.set(
+            win.next_frame()
+                .map(|next| next.max(next_frame_at(last_frame))),
+        );
     }
 
     Ok(())
@@ -0,0 +1,238 @@
+use schemars::JsonSchema;
+use serde::de::Error as _;
+use serde_derive::{Deserialize, Serialize};
+
+use crate::object::Object;
+use crate::stylesheet::Stylesheet;
+use crate::template::{Render, Template};
+use crate::theme::Theme;
+
+/// A single message sent to viereck
+#[derive(Debug, Clone)]
+pub enum Message<S = crate::style::Style> {
+    /// The objects to draw, replacing the previous ones
+    Objects(Vec<Object<S>>),
+    /// Objects sent with options, like the slot they are for
+    Update(Update<S>),
+    /// Replace the theme and redraw, written as `{"theme": {...}}`
+    Theme(Theme),
+    /// Replace the stylesheet and redraw, written as `{"stylesheet": [...]}`
+    Stylesheet(Stylesheet),
+    /// Register a template, written as `{"template": {"name": ..., "objects": [...]}}`
+    Template(Template),
+    /// Draw a registered template with the given data, written as
+    /// `{"render": "battery", "data": {...}}`
+    Render(Render),
+    /// Control the window itself, written as `{"cmd": "hide"}`
+    Command(Command),
+}
+
+/// What a `{"cmd": ...}` message does to the window
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(tag = "cmd", rename_all = "lowercase")]
+pub enum Command {
+    Hide,
+    Show,
+    Move {
+        x: i16,
+        y: i16,
+    },
+    Resize {
+        width: u16,
+        height: u16,
+    },
+    Raise,
+    #[serde(alias = "quit")]
+    Exit,
+}
+
+/// Objects with options, written as
+/// `{"slot": "right", "objects": [...], "timeout": 3}`
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct Update<S = crate::style::Style> {
+    /// Replace only what was sent for this slot instead of all objects
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub slot: Option<String>,
+    pub objects: Vec<Object<S>>,
+    /// Seconds until the window is hidden, instead of those of `--timeout`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<f64>,
+}
+
+impl<S: JsonSchema> JsonSchema for Message<S> {
+    fn schema_name() -> String {
+        "Message".to_string()
+    }
+
+    fn json_schema(gen: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+        use schemars::schema::{InstanceType, Schema, SchemaObject, SubschemaValidation};
+
+        /// An object with `key` as its only property
+        fn wrapped<T: JsonSchema>(gen: &mut schemars::gen::SchemaGenerator, key: &str) -> Schema {
+            let mut schema = SchemaObject {
+                instance_type: Some(InstanceType::Object.into()),
+                ..Default::default()
+            };
+            let object = schema.object();
+            object
+                .properties
+                .insert(key.to_string(), gen.subschema_for::<T>());
+            object.required.insert(key.to_string());
+            object.additional_properties = Some(Box::new(Schema::Bool(false)));
+            schema.into()
+        }
+
+        // `{"cmd": "exit"}` can also be written as `{"cmd": "quit"}`, which
+        // the derived schema of `Command` doesn't know
+        let mut quit = SchemaObject {
+            instance_type: Some(InstanceType::Object.into()),
+            ..Default::default()
+        };
+        let command = SchemaObject {
+            instance_type: Some(InstanceType::String.into()),
+            enum_values: Some(vec!["quit".into()]),
+            ..Default::default()
+        };
+        quit.object()
+            .properties
+            .insert("cmd".to_string(), command.into());
+        quit.object().required.insert("cmd".to_string());
+
+        SchemaObject {
+            subschemas: Some(Box::new(SubschemaValidation {
+                any_of: Some(vec![
+                    gen.subschema_for::<Vec<Object<S>>>(),
+                    gen.subschema_for::<Update<S>>(),
+                    gen.subschema_for::<Render>(),
+                    gen.subschema_for::<Command>(),
+                    quit.into(),
+                    wrapped::<Theme>(gen, "theme"),
+                    wrapped::<Stylesheet>(gen, "stylesheet"),
+                    wrapped::<Template>(gen, "template"),
+                ]),
+                ..Default::default()
+            })),
+            ..Default::default()
+        }
+        .into()
+    }
+}
+
+impl<S: serde::Serialize> serde::Serialize for Message<S> {
+    fn serialize<Ser: serde::Serializer>(&self, serializer: Ser) -> Result<Ser::Ok, Ser::Error> {
+        use serde::ser::SerializeMap;
+
+        match self {
+            Message::Objects(objects) => objects.serialize(serializer),
+            Message::Update(update) => update.serialize(serializer),
+            Message::Command(command) => command.serialize(serializer),
+            Message::Theme(theme) => {
+                let mut map = serializer.serialize_map(Some(1))?;
+                map.serialize_entry("theme", theme)?;
+                map.end()
+            }
+            Message::Stylesheet(stylesheet) => {
+                let mut map = serializer.serialize_map(Some(1))?;
+                map.serialize_entry("stylesheet", stylesheet)?;
+                map.end()
+            }
+            Message::Template(template) => {
+                let mut map = serializer.serialize_map(Some(1))?;
+                map.serialize_entry("template", template)?;
+                map.end()
+            }
+            Message::Render(render) => render.serialize(serializer),
+        }
+    }
+}
+
+impl<'de, S: serde::de::DeserializeOwned> serde::Deserialize<'de> for Message<S> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(MessageVisitor(std::marker::PhantomData))
+    }
+}
+
+/// The optional property `name` of a message, removed from `rest`
+fn take<T, E>(
+    rest: &mut serde_json::Map<String, serde_json::Value>,
+    name: &str,
+) -> Result<Option<T>, E>
+where
+    T: serde::de::DeserializeOwned,
+    E: serde::de::Error,
+{
+    match rest.remove(name) {
+        None | Some(serde_json::Value::Null) => Ok(None),
+        Some(value) => serde_json::from_value(value).map(Some).map_err(E::custom),
+    }
+}
+
+/// Reads the objects of a message straight from the input, and only the
+/// small rest of it into a map to tell which message it is
+///
+/// Unlike an untagged enum this keeps the errors of the objects themselves.
+struct MessageVisitor<S>(std::marker::PhantomData<S>);
+
+impl<'de, S: serde::de::DeserializeOwned> serde::de::Visitor<'de> for MessageVisitor<S> {
+    type Value = Message<S>;
+
+    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str("an array of objects or a message like {\"theme\": {...}}")
+    }
+
+    fn visit_seq<A: serde::de::SeqAccess<'de>>(self, seq: A) -> Result<Self::Value, A::Error> {
+        serde::Deserialize::deserialize(serde::de::value::SeqAccessDeserializer::new(seq))
+            .map(Message::Objects)
+    }
+
+    fn visit_map<A: serde::de::MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        use serde_json::{Map, Value};
+
+        let mut objects = None;
+        let mut wrapped = None;
+        let mut rest = Map::new();
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "objects" if objects.is_some() => return Err(A::Error::duplicate_field("objects")),
+                "objects" => objects = Some(map.next_value::<Vec<Object<S>>>()?),
+                "theme" | "stylesheet" | "template" if wrapped.is_some() => {
+                    return Err(A::Error::custom(
+                        "Expected only one of `theme`, `stylesheet` and `template`",
+                    ))
+                }
+                "theme" => wrapped = Some(Message::Theme(map.next_value()?)),
+                "stylesheet" => wrapped = Some(Message::Stylesheet(map.next_value()?)),
+                "template" => wrapped = Some(Message::Template(map.next_value()?)),
+                _ => {
+                    rest.insert(key, map.next_value()?);
+                }
+            }
+        }
+
+        if rest.contains_key("cmd") {
+            serde_json::from_value(Value::Object(rest))
+                .map(Message::Command)
+                .map_err(A::Error::custom)
+        } else if rest.contains_key("render") {
+            serde_json::from_value(Value::Object(rest))
+                .map(Message::Render)
+                .map_err(A::Error::custom)
+        } else if let Some(objects) = objects {
+            Ok(Message::Update(Update {
+                slot: take(&mut rest, "slot")?,
+                objects,
+                timeout: take(&mut rest, "timeout")?,
+            }))
+        } else {
+            match (wrapped, rest.keys().next()) {
+                (Some(message), None) => Ok(message),
+                (None, Some(kind)) if rest.len() == 1 => {
+                    Err(A::Error::custom(format!("Unknown message {}", kind)))
+                }
+                _ => Err(A::Error::custom(
+                    "Expected an array of objects or a message like {\"theme\": {...}}",
+                )),
+            }
+        }
+    }
+}
@@ -1,71 +1,643 @@
+use schemars::JsonSchema;
 use serde_derive::{Deserialize, Serialize};
+use std::collections::HashMap;
 
-#[derive(Serialize, Deserialize, Debug)]
-#[serde(remote = "piet::Color")]
-pub enum ColorDef {
-    Rgba32(u32),
-}
+use crate::color::Color;
 
-mod opt_external_color {
-    use super::ColorDef;
-    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+/// (De)serializes a `piet::Color`, use it with `#[serde(with = "ColorDef")]`
+///
+/// Colors are written as `#rrggbbaa` and read in any syntax `color::parse`
+/// understands, or in the older `{"Rgba32": 4278190335}` form. Unlike
+/// `Color`, they can not refer to the theme.
+#[derive(Debug)]
+pub struct ColorDef;
 
-    pub fn serialize<S>(value: &Option<piet::Color>, serializer: S) -> Result<S::Ok, S::Error>
+impl ColorDef {
+    pub fn serialize<S>(value: &piet::Color, serializer: S) -> Result<S::Ok, S::Error>
     where
-        S: Serializer,
+        S: serde::Serializer,
     {
-        #[derive(Serialize)]
-        struct Helper<'a>(#[serde(with = "ColorDef")] &'a piet::Color);
-
-        value.as_ref().map(Helper).serialize(serializer)
+        serializer.serialize_str(&crate::color::to_hex(value))
     }
 
-    pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<piet::Color>, D::Error>
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<piet::Color, D::Error>
     where
-        D: Deserializer<'de>,
+        D: serde::Deserializer<'de>,
     {
-        #[derive(Deserialize)]
-        struct Helper(#[serde(with = "ColorDef")] piet::Color);
+        use serde::de::Error;
+
+        match <Color as serde::Deserialize>::deserialize(deserializer)? {
+            Color::Value(color) => Ok(color),
+            Color::Var(name) => Err(D::Error::custom(format!(
+                "${} can not refer to the theme here",
+                name
+            ))),
+        }
+    }
+}
+
+impl JsonSchema for ColorDef {
+    fn schema_name() -> String {
+        "ColorDef".to_string()
+    }
+
+    fn json_schema(_: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+        crate::color::schema(
+            "'#rrggbb', '#rrggbbaa', '#rgb', '#rgba', a named color, 'rgb(...)', \
+             'rgba(...)', 'hsl(...)', 'hsla(...)' or 'transparent'",
+        )
+    }
+}
+
+/// The size icons are looked up with if no `icon_size` is given
+pub const DEFAULT_ICON_SIZE: u32 = 16;
+
+/// The font of texts that do not set one
+pub const DEFAULT_FONT: &str = "sans-serif";
+
+/// The font size of texts that do not set one
+pub const DEFAULT_FONT_SIZE: f64 = 12.;
+
+/// How an image is fitted into the box it got laid out in
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum ObjectFit {
+    /// Stretch the image to fill the whole box
+    Fill,
+    /// Scale the image to fit inside the box, keeping its aspect ratio
+    Contain,
+    /// Scale the image to cover the whole box, keeping its aspect ratio
+    Cover,
+    /// Do not scale the image
+    None,
+    /// Like `none` or `contain`, whichever results in a smaller image
+    ScaleDown,
+}
+
+impl Default for ObjectFit {
+    fn default() -> ObjectFit {
+        ObjectFit::Fill
+    }
+}
+
+impl ObjectFit {
+    /// Place an image of the given size inside of `bounds`
+    pub fn place(
+        self,
+        position: ObjectPosition,
+        (width, height): (f64, f64),
+        bounds: kurbo::Rect,
+    ) -> kurbo::Rect {
+        if width <= 0. || height <= 0. {
+            return bounds;
+        }
+
+        let contain = (bounds.width() / width).min(bounds.height() / height);
+        let (width, height) = match self {
+            ObjectFit::Fill => return bounds,
+            ObjectFit::Contain => (width * contain, height * contain),
+            ObjectFit::Cover => {
+                let cover = (bounds.width() / width).max(bounds.height() / height);
+                (width * cover, height * cover)
+            }
+            ObjectFit::None => (width, height),
+            ObjectFit::ScaleDown => (width * contain.min(1.), height * contain.min(1.)),
+        };
+
+        kurbo::Rect::from_origin_size(
+            (
+                bounds.x0 + (bounds.width() - width) * position.x,
+                bounds.y0 + (bounds.height() - height) * position.y,
+            ),
+            (width, height),
+        )
+    }
+}
+
+/// Where to align an image inside its box, from `0.0` (left/top) to `1.0`
+/// (right/bottom)
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct ObjectPosition {
+    pub x: f64,
+    pub y: f64,
+}
+
+impl Default for ObjectPosition {
+    fn default() -> ObjectPosition {
+        ObjectPosition { x: 0.5, y: 0.5 }
+    }
+}
+
+/// How images are scaled
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Interpolation {
+    /// Best for pixel art
+    Nearest,
+    Bilinear,
+}
+
+impl Default for Interpolation {
+    fn default() -> Interpolation {
+        Interpolation::Bilinear
+    }
+}
 
-        let helper = Option::deserialize(deserializer)?;
-        Ok(helper.map(|Helper(external)| external))
+impl From<Interpolation> for piet::InterpolationMode {
+    fn from(interpolation: Interpolation) -> piet::InterpolationMode {
+        match interpolation {
+            Interpolation::Nearest => piet::InterpolationMode::NearestNeighbor,
+            Interpolation::Bilinear => piet::InterpolationMode::Bilinear,
+        }
+    }
+}
+
+/// What to do with text wider than its box
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Overflow {
+    /// Draw the text past its box
+    Visible,
+    /// Cut the text off at the edge of its box
+    Clip,
+    /// Scroll the text through its box, as set by `marquee`
+    Marquee,
+}
+
+impl Default for Overflow {
+    fn default() -> Overflow {
+        Overflow::Visible
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum MarqueeMode {
+    /// Scroll the text out and let it come back in from the other side
+    Loop,
+    /// Scroll the text back and forth between its ends
+    Bounce,
+}
+
+fn default_marquee_speed() -> f64 {
+    30.
+}
+
+fn default_marquee_pause() -> u64 {
+    1000
+}
+
+fn default_marquee_mode() -> MarqueeMode {
+    MarqueeMode::Loop
+}
+
+fn default_marquee_gap() -> f64 {
+    30.
+}
+
+/// How overflowing text is scrolled
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct Marquee {
+    /// In points per second
+    #[serde(default = "default_marquee_speed")]
+    pub speed: f64,
+    /// How long to wait at the ends, in milliseconds
+    #[serde(default = "default_marquee_pause")]
+    pub pause: u64,
+    #[serde(default = "default_marquee_mode")]
+    pub mode: MarqueeMode,
+    /// The space between the end of the text and its next start when looping
+    #[serde(default = "default_marquee_gap")]
+    pub gap: f64,
+}
+
+impl Default for Marquee {
+    fn default() -> Marquee {
+        Marquee {
+            speed: default_marquee_speed(),
+            pause: default_marquee_pause(),
+            mode: default_marquee_mode(),
+            gap: default_marquee_gap(),
+        }
+    }
+}
+
+impl Marquee {
+    /// How far the text is scrolled to the left after `elapsed`
+    ///
+    /// When looping, the text is drawn a second time `text_width + gap`
+    /// after the first one.
+    pub fn offset(&self, elapsed: std::time::Duration, text_width: f64, box_width: f64) -> f64 {
+        let distance = self.distance(text_width, box_width);
+        if distance <= 0. || self.speed <= 0. {
+            return 0.;
+        }
+
+        let pause = self.pause as f64 / 1000.;
+        let scroll = distance / self.speed;
+        let elapsed = elapsed.as_secs_f64();
+
+        match self.mode {
+            MarqueeMode::Loop => {
+                let t = elapsed % (pause + scroll);
+                (t - pause).max(0.) * self.speed
+            }
+            MarqueeMode::Bounce => {
+                let t = elapsed % (2. * (pause + scroll));
+                if t < pause {
+                    0.
+                } else if t < pause + scroll {
+                    (t - pause) * self.speed
+                } else if t < 2. * pause + scroll {
+                    distance
+                } else {
+                    distance - (t - 2. * pause - scroll) * self.speed
+                }
+            }
+        }
+    }
+
+    /// How long after `elapsed` the text starts moving again, zero while it
+    /// is moving and `None` if it never does
+    pub fn until_moving(
+        &self,
+        elapsed: std::time::Duration,
+        text_width: f64,
+        box_width: f64,
+    ) -> Option<std::time::Duration> {
+        let distance = self.distance(text_width, box_width);
+        if distance <= 0. || self.speed <= 0. {
+            return None;
+        }
+
+        // Both modes alternate between a pause and scrolling `distance`
+        let pause = self.pause as f64 / 1000.;
+        let t = elapsed.as_secs_f64() % (pause + distance / self.speed);
+        Some(std::time::Duration::from_secs_f64((pause - t).max(0.)))
+    }
+
+    /// How far the text scrolls between two pauses
+    fn distance(&self, text_width: f64, box_width: f64) -> f64 {
+        match self.mode {
+            MarqueeMode::Loop => text_width + self.gap,
+            MarqueeMode::Bounce => text_width - box_width,
+        }
+    }
+}
+
+/// A property that can be transitioned between updates
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum TransitionProperty {
+    /// The position and size of the object
+    Layout,
+    Background,
+    /// The text color or tint of an image
+    Color,
+    Opacity,
+}
+
+fn all_transition_properties() -> Vec<TransitionProperty> {
+    vec![
+        TransitionProperty::Layout,
+        TransitionProperty::Background,
+        TransitionProperty::Color,
+        TransitionProperty::Opacity,
+    ]
+}
+
+/// How an object with an `id` changes from one update to the next
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct Transition {
+    /// In milliseconds
+    pub duration: u64,
+    #[serde(default)]
+    pub easing: crate::animation::Easing,
+    /// Which properties to animate, all of them by default
+    #[serde(default = "all_transition_properties")]
+    pub properties: Vec<TransitionProperty>,
+}
+
+/// Properties every kind of object has
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct Attributes {
+    /// Identifies an object across updates and in stylesheets
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    /// Classes to select the object by in stylesheets
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub class: Vec<String>,
+    /// From 0.0 (invisible) to 1.0 (opaque)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub opacity: Option<f64>,
+    /// Animate changes to this object, needs an `id`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transition: Option<Transition>,
+    /// Keyframes played on their own, keeps running across updates
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub animation: Option<crate::animation::Animation>,
+    /// A number like a percentage the `when` conditions are checked against
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value: Option<f64>,
+    /// Overrides for ranges of `value`, the first one matching applies
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub when: Vec<Condition>,
+}
+
+/// Overrides applied when the `value` of an object is in a range
+///
+/// The range includes `min` but not `max`, either can be left out.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct Condition {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max: Option<f64>,
+    /// Background of a container
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub background: Option<Color>,
+    /// Color of a text or container, or the tint of an image
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub color: Option<Color>,
+    /// Added to the classes, so stylesheets can select on them
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub class: Vec<String>,
+}
+
+impl Condition {
+    pub fn matches(&self, value: f64) -> bool {
+        self.min.map_or(true, |min| value >= min) && self.max.map_or(true, |max| value < max)
+    }
+}
+
+/// The text properties a container passes down to the objects inside it
+#[derive(Debug, Clone)]
+pub struct Inherited {
+    pub font: Option<String>,
+    pub font_size: Option<f64>,
+    pub color: Option<Color>,
+}
+
+impl Default for Inherited {
+    /// What the objects at the root inherit
+    fn default() -> Inherited {
+        Inherited {
+            font: Some(DEFAULT_FONT.to_string()),
+            font_size: Some(DEFAULT_FONT_SIZE),
+            color: Some(Color::Value(piet::Color::BLACK)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(tag = "type")]
-pub enum Object<S = stretch::style::Style> {
+pub enum Object<S = crate::style::Style> {
     Container {
         children: Vec<Object<S>>,
         style: S,
-        #[serde(default, with = "opt_external_color")]
-        background: Option<piet::Color>,
+        #[serde(default)]
+        background: Option<Color>,
         corner_radius: Option<f64>,
+        /// Inherited by the texts inside
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        font: Option<String>,
+        /// Inherited by the texts inside
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        font_size: Option<f64>,
+        /// Inherited by the texts inside
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        color: Option<Color>,
+        /// Names a place in the layout whose children are sent on their own
+        /// with `{"slot": ..., "objects": [...]}`
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        slot: Option<String>,
+        #[serde(flatten)]
+        attributes: Attributes,
     },
     Text {
-        font: String,
+        /// Inherited from the parent, or `sans-serif`
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        font: Option<String>,
         text: String,
-        font_size: f64,
-        #[serde(with = "ColorDef")]
-        color: piet::Color,
+        /// Inherited from the parent, or 12
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        font_size: Option<f64>,
+        /// Inherited from the parent, or black
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        color: Option<Color>,
         style: S,
+        #[serde(default)]
+        overflow: Overflow,
+        /// How to scroll the text if `overflow` is `marquee`
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        marquee: Option<Marquee>,
+        #[serde(flatten)]
+        attributes: Attributes,
     },
     Image {
         style: S,
-        path: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        path: Option<String>,
+        /// An inline SVG document
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        svg: Option<String>,
+        /// A picture as a data URI, base64 encoded as in
+        /// `data:image/png;base64,...` or percent-encoded
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        data: Option<String>,
+        /// The name of an icon in the icon theme, e.g. `battery-low-symbolic`
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        icon: Option<String>,
+        /// The preferred size of the icon, defaults to 16
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        icon_size: Option<u32>,
+        /// The icon theme to search first, defaults to the one configured for
+        /// GTK
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        icon_theme: Option<String>,
+        #[serde(default)]
+        object_fit: ObjectFit,
+        #[serde(default)]
+        object_position: ObjectPosition,
+        #[serde(default)]
+        interpolation: Interpolation,
+        /// Fill the image with this color, using it only as a mask
+        #[serde(default)]
+        tint: Option<Color>,
+        #[serde(flatten)]
+        attributes: Attributes,
     },
 }
 
+impl<S> Object<S> {
+    pub fn get_type_name(&self) -> &'static str {
+        match self {
+            Self::Container { .. } => "Container",
+            Self::Text { .. } => "Text",
+            Self::Image { .. } => "Image",
+        }
+    }
+
+    pub fn get_attributes(&self) -> &Attributes {
+        match self {
+            Self::Container { attributes, .. } => attributes,
+            Self::Text { attributes, .. } => attributes,
+            Self::Image { attributes, .. } => attributes,
+        }
+    }
+
+    pub fn get_attributes_mut(&mut self) -> &mut Attributes {
+        match self {
+            Self::Container { attributes, .. } => attributes,
+            Self::Text { attributes, .. } => attributes,
+            Self::Image { attributes, .. } => attributes,
+        }
+    }
+
+    pub fn get_style_mut(&mut self) -> &mut S {
+        match self {
+            Self::Container { style, .. } => style,
+            Self::Text { style, .. } => style,
+            Self::Image { style, .. } => style,
+        }
+    }
+
+    /// Replace the children of the slots in this object by what was sent for
+    /// them, slots nothing was sent for keep their own children
+    pub fn fill_slots(&mut self, slots: &HashMap<String, Vec<Object<S>>>)
+    where
+        S: Clone,
+    {
+        if let Self::Container { children, slot, .. } = self {
+            match slot.as_ref().and_then(|slot| slots.get(slot)) {
+                Some(content) => *children = content.clone(),
+                None => {
+                    for child in children {
+                        child.fill_slots(slots);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Apply the first condition matching the value of this object and of
+    /// every object inside it
+    pub fn apply_conditions(&mut self) {
+        let attributes = self.get_attributes_mut();
+        let condition = attributes.value.and_then(|value| {
+            attributes
+                .when
+                .iter()
+                .find(|condition| condition.matches(value))
+                .cloned()
+        });
+
+        if let Some(condition) = condition {
+            attributes.class.extend(condition.class);
+
+            match self {
+                Self::Container {
+                    background, color, ..
+                } => {
+                    if condition.background.is_some() {
+                        *background = condition.background;
+                    }
+                    if condition.color.is_some() {
+                        *color = condition.color;
+                    }
+                }
+                Self::Text { color, .. } => {
+                    if condition.color.is_some() {
+                        *color = condition.color;
+                    }
+                }
+                Self::Image { tint, .. } => {
+                    if condition.color.is_some() {
+                        *tint = condition.color;
+                    }
+                }
+            }
+        }
+
+        if let Self::Container { children, .. } = self {
+            for child in children {
+                child.apply_conditions();
+            }
+        }
+    }
+
+    /// Take the text properties this object does not set from its parent
+    ///
+    /// Returns what the children of this object inherit.
+    pub fn inherit(&mut self, inherited: &Inherited) -> Inherited {
+        match self {
+            Self::Container {
+                font,
+                font_size,
+                color,
+                ..
+            }
+            | Self::Text {
+                font,
+                font_size,
+                color,
+                ..
+            } => {
+                if font.is_none() {
+                    *font = inherited.font.clone();
+                }
+                if font_size.is_none() {
+                    *font_size = inherited.font_size;
+                }
+                if color.is_none() {
+                    *color = inherited.color.clone();
+                }
+
+                Inherited {
+                    font: font.clone(),
+                    font_size: *font_size,
+                    color: color.clone(),
+                }
+            }
+            Self::Image { .. } => inherited.clone(),
+        }
+    }
+
+    /// Every color of this object, including those of its animation but not
+    /// of its children
+    pub fn get_colors(&self) -> Vec<&Color> {
+        let mut colors: Vec<&Color> = match self {
+            Self::Container {
+                background, color, ..
+            } => background.iter().chain(color.iter()).collect(),
+            Self::Text { color, .. } => color.iter().collect(),
+            Self::Image { tint, .. } => tint.iter().collect(),
+        };
+
+        if let Some(animation) = &self.get_attributes().animation {
+            for keyframe in &animation.keyframes {
+                colors.extend(keyframe.background.iter().chain(keyframe.color.iter()));
+            }
+        }
+
+        for condition in &self.get_attributes().when {
+            colors.extend(condition.background.iter().chain(condition.color.iter()));
+        }
+
+        colors
+    }
+}
+
 impl Object {
     pub fn get_style(&self) -> stretch::style::Style {
         match self {
-            Self::Container { style, .. } => *style,
-            Self::Text { style, .. } => *style,
-            Self::Image { style, .. } => *style,
+            Self::Container { style, .. } => style.to_stretch(),
+            Self::Text { style, .. } => style.to_stretch(),
+            Self::Image { style, .. } => style.to_stretch(),
         }
     }
 
-    pub fn get_background(&self) -> Option<&piet::Color> {
+    pub fn get_background(&self) -> Option<&Color> {
         match self {
             Self::Container { background, .. } => background.as_ref(),
             Self::Text { .. } => None,
@@ -73,6 +645,44 @@ impl Object {
         }
     }
 
+    /// The text color, or the tint of an image
+    pub fn get_color(&self) -> Option<&Color> {
+        match self {
+            Self::Container { .. } => None,
+            Self::Text { color, .. } => color.as_ref(),
+            Self::Image { tint, .. } => tint.as_ref(),
+        }
+    }
+
+    pub fn get_image_source(&self) -> anyhow::Result<Option<crate::picture::Source<'_>>> {
+        use crate::picture::Source;
+        match self {
+            Self::Image {
+                path,
+                svg,
+                data,
+                icon,
+                icon_size,
+                icon_theme,
+                ..
+            } => match (path, svg, data, icon) {
+                (Some(path), None, None, None) => Ok(Some(Source::Path(path))),
+                (None, Some(svg), None, None) => Ok(Some(Source::Svg(svg))),
+                (None, None, Some(data), None) => Ok(Some(Source::Data(data))),
+                (None, None, None, Some(icon)) => Ok(Some(Source::Icon {
+                    name: icon,
+                    size: icon_size.unwrap_or(DEFAULT_ICON_SIZE),
+                    theme: icon_theme.as_deref(),
+                })),
+                _ => Err(anyhow::anyhow!(
+                    "An image needs exactly one of `path`, `svg`, `data` or `icon`"
+                )),
+            },
+            Self::Container { .. } => Ok(None),
+            Self::Text { .. } => Ok(None),
+        }
+    }
+
     pub fn compute_size(
         &self,
         size: stretch::geometry::Size<stretch::number::Number>,
@@ -87,28 +697,287 @@ impl Object {
                 ..
             } => {
                 let mut text_builder = piet_cairo::CairoText::new();
+                let font_size = font_size.unwrap_or(DEFAULT_FONT_SIZE);
                 let font = text_builder
-                    .new_font_by_name(&font, *font_size)
+                    .new_font_by_name(font.as_deref().unwrap_or(DEFAULT_FONT), font_size)
                     .build()
                     .unwrap();
                 let text_layout = text_builder.new_text_layout(&font, &text).build().unwrap();
                 let width = text_layout.width() as f32;
                 Ok(stretch::geometry::Size {
                     width: width.maybe_min(size.width),
-                    height: (*font_size as f32).maybe_min(size.height),
+                    height: (font_size as f32).maybe_min(size.height),
                 })
             }
-            Self::Image { path, .. } => {
-                let mut image = std::fs::File::open(&path).unwrap();
-                let img = cairo::ImageSurface::create_from_png(&mut image).unwrap();
-                let width = img.get_width() as f32;
-                let height = img.get_height() as f32;
+            Self::Image { .. } => {
+                let picture = self.get_image_source().and_then(|source| {
+                    crate::picture::Picture::load(source.expect("Image has a source"))
+                });
+                // A picture that can't be loaded takes no space, the error is
+                // logged when its node is created
+                let (width, height) = picture.map_or((0., 0.), |picture| picture.size());
                 Ok(stretch::geometry::Size {
-                    width: width.maybe_min(size.width),
-                    height: height.maybe_min(size.height),
+                    width: (width as f32).maybe_min(size.width),
+                    height: (height as f32).maybe_min(size.height),
                 })
             }
             _ => Err(Box::new(())),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::time::Duration;
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-6,
+            "{} is not {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn object_fit() {
+        use ObjectFit::*;
+
+        let bounds = kurbo::Rect::new(0., 0., 100., 50.);
+        let wider = (200., 50.);
+        let taller = (50., 100.);
+        let smaller = (20., 10.);
+        let table = [
+            (Fill, wider, (0., 0., 100., 50.)),
+            (Fill, taller, (0., 0., 100., 50.)),
+            (Contain, wider, (0., 12.5, 100., 37.5)),
+            (Contain, taller, (37.5, 0., 62.5, 50.)),
+            (Contain, smaller, (0., 0., 100., 50.)),
+            (Cover, wider, (-50., 0., 150., 50.)),
+            (Cover, taller, (0., -75., 100., 125.)),
+            (None, wider, (-50., 0., 150., 50.)),
+            (None, taller, (25., -25., 75., 75.)),
+            (None, smaller, (40., 20., 60., 30.)),
+            (ScaleDown, wider, (0., 12.5, 100., 37.5)),
+            (ScaleDown, taller, (37.5, 0., 62.5, 50.)),
+            (ScaleDown, smaller, (40., 20., 60., 30.)),
+        ];
+        for (fit, size, (x0, y0, x1, y1)) in table.iter() {
+            assert_eq!(
+                fit.place(ObjectPosition::default(), *size, bounds),
+                kurbo::Rect::new(*x0, *y0, *x1, *y1),
+                "{:?} of {:?}",
+                fit,
+                size
+            );
+        }
+    }
+
+    #[test]
+    fn object_position() {
+        let bounds = kurbo::Rect::new(10., 20., 110., 70.);
+        let start = ObjectPosition { x: 0., y: 0. };
+        let end = ObjectPosition { x: 1., y: 1. };
+        let table = [
+            (
+                ObjectFit::Contain,
+                start,
+                (200., 50.),
+                (10., 20., 110., 45.),
+            ),
+            (ObjectFit::Contain, end, (200., 50.), (10., 45., 110., 70.)),
+            (ObjectFit::Contain, start, (50., 100.), (10., 20., 35., 70.)),
+            (ObjectFit::Contain, end, (50., 100.), (85., 20., 110., 70.)),
+            (ObjectFit::Cover, start, (200., 50.), (10., 20., 210., 70.)),
+            (ObjectFit::Cover, end, (200., 50.), (-90., 20., 110., 70.)),
+            (ObjectFit::Cover, end, (50., 100.), (10., -130., 110., 70.)),
+            (ObjectFit::None, start, (20., 10.), (10., 20., 30., 30.)),
+            (ObjectFit::ScaleDown, end, (20., 10.), (90., 60., 110., 70.)),
+            (ObjectFit::Fill, end, (20., 10.), (10., 20., 110., 70.)),
+        ];
+        for (fit, position, size, (x0, y0, x1, y1)) in table.iter() {
+            assert_eq!(
+                fit.place(*position, *size, bounds),
+                kurbo::Rect::new(*x0, *y0, *x1, *y1),
+                "{:?} at {:?} of {:?}",
+                fit,
+                position,
+                size
+            );
+        }
+
+        // Without a size there is nothing to fit
+        assert_eq!(ObjectFit::Contain.place(start, (0., 10.), bounds), bounds);
+    }
+
+    #[test]
+    fn condition_ranges() {
+        let condition = |min, max| Condition {
+            min,
+            max,
+            ..Default::default()
+        };
+        let table = [
+            (condition(Some(20.), Some(50.)), 19.9, false),
+            (condition(Some(20.), Some(50.)), 20., true),
+            (condition(Some(20.), Some(50.)), 49.9, true),
+            (condition(Some(20.), Some(50.)), 50., false),
+            (condition(None, Some(50.)), -100., true),
+            (condition(None, Some(50.)), 50., false),
+            (condition(Some(20.), None), 20., true),
+            (condition(Some(20.), None), 1000., true),
+            (condition(Some(20.), None), 19.9, false),
+            (condition(None, None), 0., true),
+        ];
+        for (condition, value, matches) in table.iter() {
+            assert_eq!(
+                condition.matches(*value),
+                *matches,
+                "{} in {:?}",
+                value,
+                condition
+            );
+        }
+    }
+
+    /// Applies the conditions to an object given as JSON
+    fn apply(object: serde_json::Value) -> serde_json::Value {
+        let mut object: Object = serde_json::from_value(object).unwrap();
+        object.apply_conditions();
+        serde_json::to_value(object).unwrap()
+    }
+
+    #[test]
+    fn apply_conditions() {
+        // Like the battery thresholds of the panel example, the ranges
+        // overlap and the earlier, narrower one wins where they do
+        let battery = |value: Option<f64>| {
+            json!({
+                "type": "Container",
+                "style": {},
+                "background": "#00ff00",
+                "class": ["battery"],
+                "value": value,
+                "when": [
+                    {"max": 21., "background": "$red", "class": ["critical"]},
+                    {"max": 51., "background": "$orange"},
+                ],
+                "children": [],
+            })
+        };
+        let table = [
+            (Some(0.), "$red", json!(["battery", "critical"])),
+            (Some(20.9), "$red", json!(["battery", "critical"])),
+            (Some(21.), "$orange", json!(["battery"])),
+            (Some(50.9), "$orange", json!(["battery"])),
+            (Some(51.), "#00ff00ff", json!(["battery"])),
+            (None, "#00ff00ff", json!(["battery"])),
+        ];
+        for (value, background, class) in table.iter() {
+            let object = apply(battery(*value));
+            assert_eq!(object["background"], *background, "{:?}", value);
+            assert_eq!(object["class"], *class, "{:?}", value);
+        }
+    }
+
+    #[test]
+    fn apply_conditions_to_children() {
+        let object = apply(json!({
+            "type": "Container",
+            "style": {},
+            "value": 10.,
+            "when": [{"max": 20., "color": "$fg"}],
+            "children": [
+                {
+                    "type": "Text",
+                    "style": {},
+                    "text": "10%",
+                    "value": 10.,
+                    "when": [{"min": 50., "color": "$red"}, {"color": "$green"}],
+                },
+                {
+                    "type": "Image",
+                    "style": {},
+                    "icon": "battery",
+                    "value": 10.,
+                    "when": [{"max": 20., "color": "$red", "background": "$bg"}],
+                },
+            ],
+        }));
+        assert_eq!(object["color"], "$fg");
+        assert_eq!(object["children"][0]["color"], "$green");
+        assert_eq!(object["children"][1]["tint"], "$red");
+        assert!(object["children"][1].get("background").is_none());
+    }
+
+    #[test]
+    fn marquee_loop() {
+        // Scrolls 120 points in 4 seconds after pausing for one
+        let marquee = Marquee::default();
+        let table = [
+            (0, 0., 1.),
+            (400, 0., 0.6),
+            (999, 0., 0.001),
+            (1000, 0., 0.),
+            (1500, 15., 0.),
+            (3000, 60., 0.),
+            (4999, 119.97, 0.),
+            (5000, 0., 1.),
+            (5250, 0., 0.75),
+            (6500, 15., 0.),
+        ];
+        for (elapsed, offset, until_moving) in table.iter() {
+            let elapsed = Duration::from_millis(*elapsed);
+            assert_close(marquee.offset(elapsed, 90., 60.), *offset);
+            let until = marquee.until_moving(elapsed, 90., 60.).unwrap();
+            assert_close(until.as_secs_f64(), *until_moving);
+        }
+    }
+
+    #[test]
+    fn marquee_bounce() {
+        // Scrolls 30 points each way in a second, pausing for one at both ends
+        let marquee = Marquee {
+            mode: MarqueeMode::Bounce,
+            ..Marquee::default()
+        };
+        let table = [
+            (0, 0., 1.),
+            (999, 0., 0.001),
+            (1000, 0., 0.),
+            (1500, 15., 0.),
+            (2000, 30., 1.),
+            (2500, 30., 0.5),
+            (3000, 30., 0.),
+            (3500, 15., 0.),
+            (4000, 0., 1.),
+            (5500, 15., 0.),
+        ];
+        for (elapsed, offset, until_moving) in table.iter() {
+            let elapsed = Duration::from_millis(*elapsed);
+            assert_close(marquee.offset(elapsed, 90., 60.), *offset);
+            let until = marquee.until_moving(elapsed, 90., 60.).unwrap();
+            assert_close(until.as_secs_f64(), *until_moving);
+        }
+    }
+
+    #[test]
+    fn marquee_at_rest() {
+        let elapsed = Duration::from_millis(1500);
+        let bounce = Marquee {
+            mode: MarqueeMode::Bounce,
+            ..Marquee::default()
+        };
+        assert_close(bounce.offset(elapsed, 50., 60.), 0.);
+        assert_eq!(bounce.until_moving(elapsed, 50., 60.), None);
+
+        let stopped = Marquee {
+            speed: 0.,
+            ..Marquee::default()
+        };
+        assert_close(stopped.offset(elapsed, 90., 60.), 0.);
+        assert_eq!(stopped.until_moving(elapsed, 90., 60.), None);
+    }
+}
@@ -0,0 +1,936 @@
+use anyhow::Context as AnyhowContext;
+use image::AnimationDecoder;
+use resvg::usvg;
+use std::cell::{Cell, RefCell};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::rc::Rc;
+use std::time::{Duration, Instant, SystemTime};
+
+/// How many decoded pictures are kept around
+const CACHE_SIZE: usize = 16;
+
+/// Frames shorter than this are shown for `DEFAULT_FRAME_DELAY`, as browsers do
+const MIN_FRAME_DELAY: Duration = Duration::from_millis(20);
+const DEFAULT_FRAME_DELAY: Duration = Duration::from_millis(100);
+
+/// What a cached picture was loaded from: the kind of source, its content or
+/// path and the modification time of a file
+type CacheKey = (&'static str, String, Option<SystemTime>);
+
+thread_local! {
+    /// The pictures by the hash of their key, which is kept to tell a
+    /// collision from a hit
+    static CACHE: RefCell<HashMap<u64, (CacheKey, Rc<Picture>, u64)>> = RefCell::new(HashMap::new());
+    static CACHE_CLOCK: Cell<u64> = Cell::new(0);
+}
+
+/// Where the picture of an image comes from
+#[derive(Debug, Clone, Copy)]
+pub enum Source<'a> {
+    Path(&'a str),
+    Svg(&'a str),
+    /// A `data:<mime type>;base64,<data>` URI
+    Data(&'a str),
+    /// An icon looked up in an icon theme, the user's one if no `theme` is
+    /// given
+    Icon {
+        name: &'a str,
+        size: u32,
+        theme: Option<&'a str>,
+    },
+}
+
+/// The frames of an animated picture and how long each is shown
+pub struct Frames {
+    frames: Vec<(cairo::ImageSurface, Duration)>,
+    total: Duration,
+    start: Instant,
+}
+
+impl Frames {
+    /// The index of the frame shown at `now` and when the next one is due
+    pub fn frame_at(&self, now: Instant) -> (usize, Instant) {
+        let elapsed = now.saturating_duration_since(self.start);
+        let total = self.total.as_nanos();
+        let mut offset = Duration::from_nanos((elapsed.as_nanos() % total) as u64);
+
+        for (idx, (_, delay)) in self.frames.iter().enumerate() {
+            if offset < *delay {
+                return (idx, now + (*delay - offset));
+            }
+            offset -= *delay;
+        }
+
+        (0, now + self.frames[0].1)
+    }
+}
+
+/// A loaded picture, either a raster image or a vector graphic
+pub enum Picture {
+    Raster(cairo::ImageSurface),
+    /// An animated GIF
+    Animated(Frames),
+    Svg(usvg::Tree),
+}
+
+impl std::fmt::Debug for Picture {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let kind = match self {
+            Picture::Raster(_) => "Raster",
+            Picture::Animated(_) => "Animated",
+            Picture::Svg(_) => "Svg",
+        };
+        let (width, height) = self.size();
+        write!(f, "Picture::{}({}x{})", kind, width, height)
+    }
+}
+
+/// Get a picture from the cache, or load and insert it
+fn cached(
+    kind: &'static str,
+    content: &str,
+    modified: Option<SystemTime>,
+    load: impl FnOnce() -> anyhow::Result<Picture>,
+) -> anyhow::Result<Rc<Picture>> {
+    let mut hasher = DefaultHasher::new();
+    (kind, content, modified).hash(&mut hasher);
+    let hash = hasher.finish();
+
+    let now = CACHE_CLOCK.with(|clock| {
+        clock.set(clock.get() + 1);
+        clock.get()
+    });
+
+    let hit = CACHE.with(|cache| {
+        cache
+            .borrow_mut()
+            .get_mut(&hash)
+            .filter(|((cached_kind, cached_content, cached_modified), _, _)| {
+                *cached_kind == kind && cached_content == content && *cached_modified == modified
+            })
+            .map(|(_, picture, last_used)| {
+                *last_used = now;
+                picture.clone()
+            })
+    });
+
+    if let Some(picture) = hit {
+        return Ok(picture);
+    }
+
+    let picture = Rc::new(load()?);
+
+    CACHE.with(|cache| {
+        let mut cache = cache.borrow_mut();
+        // A colliding picture is replaced instead of evicting another one
+        if cache.len() >= CACHE_SIZE && !cache.contains_key(&hash) {
+            let oldest = cache
+                .iter()
+                .min_by_key(|(_, (_, _, last_used))| *last_used)
+                .map(|(hash, _)| *hash);
+            if let Some(oldest) = oldest {
+                cache.remove(&oldest);
+            }
+        }
+        let key = (kind, content.to_string(), modified);
+        cache.insert(hash, (key, picture.clone(), now));
+    });
+
+    Ok(picture)
+}
+
+impl Picture {
+    /// Load a picture from the given source
+    ///
+    /// Pictures are cached by their content (or path and modification time),
+    /// so re-sending the same picture does not decode it again. This also
+    /// keeps animations running across updates.
+    pub fn load(source: Source) -> anyhow::Result<Rc<Picture>> {
+        match source {
+            Source::Path(path) => {
+                let modified = std::fs::metadata(path)
+                    .and_then(|meta| meta.modified())
+                    .context(format!("Tried opening image file: {}", path))?;
+                cached("path", path, Some(modified), || Picture::from_path(path))
+            }
+            Source::Svg(svg) => cached("svg", svg, None, || Picture::from_svg(svg.as_bytes())),
+            Source::Icon { name, size, theme } => {
+                let path = crate::icon::lookup(name, size, theme).with_context(|| match theme {
+                    Some(theme) => format!("Could not find icon {} in theme {}", name, theme),
+                    None => format!("Could not find icon {}", name),
+                })?;
+                let path = path
+                    .to_str()
+                    .with_context(|| format!("Icon path is not valid UTF-8: {:?}", path))?;
+                Picture::load(Source::Path(path))
+            }
+            Source::Data(data) => cached("data", data, None, || Picture::from_data_uri(data)),
+        }
+    }
+
+    /// Decode a `data:<media type>[;base64],<data>` URI
+    ///
+    /// The data is base64 encoded with `;base64` and percent-encoded without,
+    /// which suits SVGs. Parameters like `;charset=utf-8` are ignored.
+    pub fn from_data_uri(uri: &str) -> anyhow::Result<Picture> {
+        let uri = uri
+            .strip_prefix("data:")
+            .context("Image data needs to start with `data:`")?;
+        let (header, data) = match uri.find(',') {
+            Some(idx) => (&uri[..idx], &uri[idx + 1..]),
+            None => {
+                return Err(anyhow::anyhow!(
+                    "Image data is missing a `,` before the data"
+                ))
+            }
+        };
+
+        let mut parameters = header.split(';');
+        let media_type = parameters.next().unwrap_or_default().trim();
+        if !media_type.starts_with("image/") {
+            return Err(anyhow::anyhow!(
+                "Image data has the unsupported media type `{}`, e.g. `image/png` is needed",
+                media_type
+            ));
+        }
+
+        let data = if parameters.any(|parameter| parameter.trim() == "base64") {
+            base64::decode(data.trim()).context("Tried decoding base64 image data")?
+        } else {
+            percent_decode(data).context("Tried decoding percent-encoded image data")?
+        };
+
+        match media_type {
+            "image/svg+xml" => Picture::from_svg(&data),
+            _ => Picture::from_bytes(&data),
+        }
+    }
+
+    pub fn from_path(path: &str) -> anyhow::Result<Picture> {
+        if path.ends_with(".svg") || path.ends_with(".svgz") {
+            let tree = usvg::Tree::from_file(path, &usvg::Options::default())
+                .map_err(|e| anyhow::anyhow!(e.to_string()))
+                .context(format!("Tried opening svg file: {}", path))?;
+            return Ok(Picture::Svg(tree));
+        }
+
+        let data = std::fs::read(path).context(format!("Tried opening image file: {}", path))?;
+        Picture::from_bytes(&data).context(format!("Tried decoding image file: {}", path))
+    }
+
+    /// Decode a picture, the format is detected from its magic bytes
+    ///
+    /// Anything that is not a raster format known to the `image` crate is
+    /// tried as an SVG.
+    pub fn from_bytes(data: &[u8]) -> anyhow::Result<Picture> {
+        let format = match image::guess_format(data) {
+            Ok(format) => format,
+            Err(_) => {
+                return Picture::from_svg(data).context("Unknown image format");
+            }
+        };
+
+        if format == image::ImageFormat::GIF {
+            return Picture::from_gif(data);
+        }
+        if format == image::ImageFormat::PNG {
+            return Picture::from_png(data);
+        }
+
+        let image = image::load_from_memory_with_format(data, format)?;
+        Ok(Picture::Raster(surface_from_rgba(&image.to_rgba())?))
+    }
+
+    /// Decode a GIF, keeping all its frames if it is animated
+    pub fn from_gif(data: &[u8]) -> anyhow::Result<Picture> {
+        let decoder = image::gif::Decoder::new(std::io::Cursor::new(data))?;
+        let frames = decoder
+            .into_frames()
+            .collect_frames()?
+            .into_iter()
+            .map(|frame| {
+                let delay = Duration::from_millis(frame.delay().to_integer() as u64);
+                Ok((surface_from_rgba(frame.buffer())?, frame_delay(delay)))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        Picture::from_frames(frames).context("GIF does not contain any frames")
+    }
+
+    /// Decode a PNG, keeping all its frames if it is an APNG
+    ///
+    /// The `image` crate only decodes the default image, so the frames are
+    /// split into PNGs of their own, decoded one by one and composed onto the
+    /// canvas as their `fcTL` chunk says.
+    pub fn from_png(data: &[u8]) -> anyhow::Result<Picture> {
+        let apng = match Apng::parse(data)? {
+            Some(apng) => apng,
+            None => {
+                let image = image::load_from_memory_with_format(data, image::ImageFormat::PNG)?;
+                return Ok(Picture::Raster(surface_from_rgba(&image.to_rgba())?));
+            }
+        };
+
+        let frames = apng
+            .compose()?
+            .iter()
+            .map(|(image, delay)| Ok((surface_from_rgba(image)?, *delay)))
+            .collect::<anyhow::Result<_>>()?;
+
+        Picture::from_frames(frames).context("APNG does not contain any frames")
+    }
+
+    /// A still picture of a single frame, an animated one of more
+    fn from_frames(mut frames: Vec<(cairo::ImageSurface, Duration)>) -> Option<Picture> {
+        match frames.len() {
+            0 => None,
+            1 => Some(Picture::Raster(frames.remove(0).0)),
+            _ => Some(Picture::Animated(Frames {
+                total: frames.iter().map(|(_, delay)| *delay).sum(),
+                frames,
+                start: Instant::now(),
+            })),
+        }
+    }
+
+    pub fn from_svg(data: &[u8]) -> anyhow::Result<Picture> {
+        let tree = usvg::Tree::from_data(data, &usvg::Options::default())
+            .map_err(|e| anyhow::anyhow!(e.to_string()))
+            .context("Tried parsing inline svg")?;
+        Ok(Picture::Svg(tree))
+    }
+
+    /// The intrinsic size of the picture
+    ///
+    /// For SVGs this is the size given by the `width` and `height` attributes,
+    /// `usvg` falls back to the `viewBox` if they are missing.
+    pub fn size(&self) -> (f64, f64) {
+        match self {
+            Picture::Raster(surface) => (surface.get_width() as f64, surface.get_height() as f64),
+            Picture::Animated(frames) => {
+                let surface = &frames.frames[0].0;
+                (surface.get_width() as f64, surface.get_height() as f64)
+            }
+            Picture::Svg(tree) => {
+                let svg = tree.svg_node();
+                if svg.size.width() > 0. && svg.size.height() > 0. {
+                    (svg.size.width(), svg.size.height())
+                } else {
+                    (svg.view_box.rect.width(), svg.view_box.rect.height())
+                }
+            }
+        }
+    }
+
+    /// When the next frame of an animated picture is due
+    pub fn next_frame(&self, now: Instant) -> Option<Instant> {
+        match self {
+            Picture::Animated(frames) => Some(frames.frame_at(now).1),
+            Picture::Raster(_) | Picture::Svg(_) => None,
+        }
+    }
+
+    /// The index of the frame shown at `now`, always `0` for still pictures
+    pub fn frame_index(&self, now: Instant) -> usize {
+        match self {
+            Picture::Animated(frames) => frames.frame_at(now).0,
+            Picture::Raster(_) | Picture::Svg(_) => 0,
+        }
+    }
+
+    /// Get a surface to draw at `now`, SVGs are rasterized at the given size
+    pub fn to_surface(
+        &self,
+        width: f64,
+        height: f64,
+        now: Instant,
+    ) -> anyhow::Result<cairo::ImageSurface> {
+        match self {
+            Picture::Raster(surface) => Ok(surface.clone()),
+            Picture::Animated(frames) => Ok(frames.frames[frames.frame_at(now).0].0.clone()),
+            Picture::Svg(tree) => {
+                let width = width.ceil().max(1.) as u32;
+                let height = height.ceil().max(1.) as u32;
+                let surface =
+                    cairo::ImageSurface::create(cairo::Format::ARgb32, width as i32, height as i32)
+                        .map_err(|e| anyhow::anyhow!("Could not create surface: {:?}", e))?;
+
+                {
+                    let context = cairo::Context::new(&surface);
+                    let size = resvg::ScreenSize::new(width, height)
+                        .context("Tried rendering svg with an empty size")?;
+                    resvg::backend_cairo::render_to_canvas(
+                        tree,
+                        &resvg::Options::default(),
+                        size,
+                        &context,
+                    );
+                }
+
+                Ok(surface)
+            }
+        }
+    }
+}
+
+/// Copy an RGBA image into a cairo surface
+///
+/// Cairo expects premultiplied alpha in native endian `ARGB32`.
+pub fn surface_from_rgba(image: &image::RgbaImage) -> anyhow::Result<cairo::ImageSurface> {
+    let (width, height) = image.dimensions();
+    let mut surface =
+        cairo::ImageSurface::create(cairo::Format::ARgb32, width as i32, height as i32)
+            .map_err(|e| anyhow::anyhow!("Could not create surface: {:?}", e))?;
+    let stride = surface.get_stride() as usize;
+
+    {
+        let mut surface_data = surface
+            .get_data()
+            .map_err(|e| anyhow::anyhow!("Could not access surface data: {:?}", e))?;
+
+        for (x, y, pixel) in image.enumerate_pixels() {
+            let [r, g, b, a] = pixel.0;
+            let premultiply = |c: u8| ((c as u16 * a as u16 + 127) / 255) as u8;
+            let argb = u32::from_be_bytes([a, premultiply(r), premultiply(g), premultiply(b)]);
+
+            let offset = y as usize * stride + x as usize * 4;
+            surface_data[offset..offset + 4].copy_from_slice(&argb.to_ne_bytes());
+        }
+    }
+
+    Ok(surface)
+}
+
+/// Fill the shape given by the alpha channel of `surface` with `color`
+///
+/// This is used to recolor monochrome (symbolic) icons.
+pub fn tint(
+    surface: &cairo::ImageSurface,
+    color: &piet::Color,
+) -> anyhow::Result<cairo::ImageSurface> {
+    let tinted = cairo::ImageSurface::create(
+        cairo::Format::ARgb32,
+        surface.get_width(),
+        surface.get_height(),
+    )
+    .map_err(|e| anyhow::anyhow!("Could not create surface: {:?}", e))?;
+
+    {
+        let rgba = color.as_rgba_u32();
+        let channel = |shift: u32| ((rgba >> shift) & 0xFF) as f64 / 255.;
+        let context = cairo::Context::new(&tinted);
+        context.set_source_rgba(channel(24), channel(16), channel(8), channel(0));
+        context.mask_surface(surface, 0., 0.);
+    }
+
+    Ok(tinted)
+}
+
+/// Replace the `%XX` escapes in `data` by the bytes they stand for
+fn percent_decode(data: &str) -> anyhow::Result<Vec<u8>> {
+    let bytes = data.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut idx = 0;
+    while idx < bytes.len() {
+        if bytes[idx] != b'%' {
+            decoded.push(bytes[idx]);
+            idx += 1;
+            continue;
+        }
+
+        let byte = bytes
+            .get(idx + 1..idx + 3)
+            .filter(|hex| hex.iter().all(u8::is_ascii_hexdigit))
+            .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok())
+            .with_context(|| format!("Invalid escape at byte {}", idx))?;
+        decoded.push(byte);
+        idx += 3;
+    }
+    Ok(decoded)
+}
+
+/// Frames shown for less than `MIN_FRAME_DELAY` are slowed down
+fn frame_delay(delay: Duration) -> Duration {
+    if delay < MIN_FRAME_DELAY {
+        DEFAULT_FRAME_DELAY
+    } else {
+        delay
+    }
+}
+
+/// Draw `source` over `target`, both with straight alpha
+fn blend_over(source: image::Rgba<u8>, target: image::Rgba<u8>) -> image::Rgba<u8> {
+    let source_alpha = source.0[3] as u32;
+    let target_alpha = target.0[3] as u32 * (255 - source_alpha) / 255;
+    let alpha = source_alpha + target_alpha;
+    if alpha == 0 {
+        return image::Rgba([0, 0, 0, 0]);
+    }
+
+    let channel = |idx: usize| {
+        ((source.0[idx] as u32 * source_alpha + target.0[idx] as u32 * target_alpha) / alpha) as u8
+    };
+    image::Rgba([channel(0), channel(1), channel(2), alpha as u8])
+}
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+const DISPOSE_BACKGROUND: u8 = 1;
+const DISPOSE_PREVIOUS: u8 = 2;
+const BLEND_OVER: u8 = 1;
+
+/// Where a frame of an APNG goes and what happens to it afterwards
+struct FrameControl {
+    width: u32,
+    height: u32,
+    x: u32,
+    y: u32,
+    delay: Duration,
+    dispose: u8,
+    blend: u8,
+}
+
+struct ApngFrame {
+    control: FrameControl,
+    /// The compressed image data, as it goes into `IDAT` chunks
+    data: Vec<Vec<u8>>,
+}
+
+/// The chunks of an APNG needed to decode its frames one by one
+struct Apng<'a> {
+    width: u32,
+    height: u32,
+    header: &'a [u8],
+    /// Chunks before the image data like `PLTE` and `tRNS`, which apply to
+    /// every frame
+    shared: Vec<(&'a [u8], &'a [u8])>,
+    frames: Vec<ApngFrame>,
+}
+
+impl<'a> Apng<'a> {
+    /// Split an APNG into its frames, `None` if it is a plain PNG
+    fn parse(data: &'a [u8]) -> anyhow::Result<Option<Apng<'a>>> {
+        if !data.starts_with(PNG_SIGNATURE) {
+            return Err(anyhow::anyhow!("Not a PNG"));
+        }
+        let mut rest = &data[PNG_SIGNATURE.len()..];
+        let mut header = None;
+        let mut animated = false;
+        let mut shared = Vec::new();
+        let mut frames: Vec<ApngFrame> = Vec::new();
+        let mut seen_data = false;
+
+        // Only data after `IEND` is ignored, a PNG ending before it is cut off
+        loop {
+            if rest.len() < 12 {
+                return Err(anyhow::anyhow!("PNG is cut off"));
+            }
+            let length = u32::from_be_bytes([rest[0], rest[1], rest[2], rest[3]]) as usize;
+            if rest.len() - 12 < length {
+                return Err(anyhow::anyhow!("PNG chunk is cut off"));
+            }
+            let kind = &rest[4..8];
+            let content = &rest[8..8 + length];
+            let crc = &rest[8 + length..12 + length];
+            if crc32(rest[4..8 + length].iter()).to_be_bytes() != crc {
+                return Err(anyhow::anyhow!(
+                    "PNG chunk {} is corrupted",
+                    String::from_utf8_lossy(kind)
+                ));
+            }
+            rest = &rest[12 + length..];
+
+            match kind {
+                b"IHDR" => header = Some(content),
+                b"acTL" => animated = true,
+                b"fcTL" => frames.push(ApngFrame {
+                    control: FrameControl::parse(content)?,
+                    data: Vec::new(),
+                }),
+                // Without a `fcTL` before it the default image is no frame
+                b"IDAT" => {
+                    seen_data = true;
+                    if let Some(frame) = frames.last_mut() {
+                        frame.data.push(content.to_vec());
+                    }
+                }
+                b"fdAT" if content.len() >= 4 => match frames.last_mut() {
+                    Some(frame) => frame.data.push(content[4..].to_vec()),
+                    None => return Err(anyhow::anyhow!("APNG frame data without fcTL")),
+                },
+                b"IEND" => break,
+                _ if !seen_data => shared.push((kind, content)),
+                _ => {}
+            }
+        }
+
+        let header = header.ok_or_else(|| anyhow::anyhow!("PNG has no IHDR chunk"))?;
+        if !animated || header.len() < 13 {
+            return Ok(None);
+        }
+
+        Ok(Some(Apng {
+            width: u32::from_be_bytes([header[0], header[1], header[2], header[3]]),
+            height: u32::from_be_bytes([header[4], header[5], header[6], header[7]]),
+            header,
+            shared,
+            frames,
+        }))
+    }
+
+    /// Every frame drawn onto the canvas as its `fcTL` chunk says, with how
+    /// long it is shown
+    fn compose(&self) -> anyhow::Result<Vec<(image::RgbaImage, Duration)>> {
+        let mut canvas = image::RgbaImage::new(self.width, self.height);
+        let mut frames = Vec::with_capacity(self.frames.len());
+        for (idx, frame) in self.frames.iter().enumerate() {
+            let image = image::load_from_memory_with_format(
+                &self.frame_png(frame),
+                image::ImageFormat::PNG,
+            )
+            .context(format!("Tried decoding APNG frame {}", idx))?
+            .to_rgba();
+            let control = &frame.control;
+            if control.x as u64 + image.width() as u64 > self.width as u64
+                || control.y as u64 + image.height() as u64 > self.height as u64
+            {
+                return Err(anyhow::anyhow!("APNG frame {} lies outside the image", idx));
+            }
+
+            let previous = match control.dispose {
+                DISPOSE_PREVIOUS if idx > 0 => Some(canvas.clone()),
+                _ => None,
+            };
+            for (x, y, pixel) in image.enumerate_pixels() {
+                let target = canvas.get_pixel_mut(control.x + x, control.y + y);
+                *target = match control.blend {
+                    BLEND_OVER => blend_over(*pixel, *target),
+                    _ => *pixel,
+                };
+            }
+            frames.push((canvas.clone(), frame_delay(control.delay)));
+
+            match (control.dispose, previous) {
+                (DISPOSE_PREVIOUS, Some(previous)) => canvas = previous,
+                (DISPOSE_BACKGROUND, _) | (DISPOSE_PREVIOUS, None) => {
+                    for y in control.y..control.y + image.height() {
+                        for x in control.x..control.x + image.width() {
+                            canvas.put_pixel(x, y, image::Rgba([0, 0, 0, 0]));
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Ok(frames)
+    }
+
+    /// A PNG of its own holding only `frame`
+    fn frame_png(&self, frame: &ApngFrame) -> Vec<u8> {
+        let mut header = self.header.to_vec();
+        header[0..4].copy_from_slice(&frame.control.width.to_be_bytes());
+        header[4..8].copy_from_slice(&frame.control.height.to_be_bytes());
+
+        let mut png = PNG_SIGNATURE.to_vec();
+        write_chunk(&mut png, b"IHDR", &header);
+        for (kind, content) in &self.shared {
+            write_chunk(&mut png, kind, content);
+        }
+        for data in &frame.data {
+            write_chunk(&mut png, b"IDAT", data);
+        }
+        write_chunk(&mut png, b"IEND", &[]);
+        png
+    }
+}
+
+impl FrameControl {
+    fn parse(content: &[u8]) -> anyhow::Result<FrameControl> {
+        if content.len() < 26 {
+            return Err(anyhow::anyhow!("APNG fcTL chunk is too short"));
+        }
+        let number = |offset: usize| {
+            u32::from_be_bytes([
+                content[offset],
+                content[offset + 1],
+                content[offset + 2],
+                content[offset + 3],
+            ])
+        };
+        let delay_num = u16::from_be_bytes([content[20], content[21]]) as u64;
+        // A denominator of 0 means hundredths of a second
+        let delay_den = match u16::from_be_bytes([content[22], content[23]]) {
+            0 => 100,
+            den => den as u64,
+        };
+
+        Ok(FrameControl {
+            width: number(4),
+            height: number(8),
+            x: number(12),
+            y: number(16),
+            delay: Duration::from_millis(delay_num * 1000 / delay_den),
+            dispose: content[24],
+            blend: content[25],
+        })
+    }
+}
+
+fn write_chunk(png: &mut Vec<u8>, kind: &[u8], content: &[u8]) {
+    png.extend_from_slice(&(content.len() as u32).to_be_bytes());
+    png.extend_from_slice(kind);
+    png.extend_from_slice(content);
+    png.extend_from_slice(&crc32(kind.iter().chain(content)).to_be_bytes());
+}
+
+/// The CRC-32 every PNG chunk ends with
+fn crc32<'a>(bytes: impl Iterator<Item = &'a u8>) -> u32 {
+    !bytes.fold(!0u32, |crc, byte| {
+        (0..8).fold(crc ^ *byte as u32, |crc, _| {
+            if crc & 1 == 1 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            }
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RED: image::Rgba<u8> = image::Rgba([255, 0, 0, 255]);
+    const GREEN: image::Rgba<u8> = image::Rgba([0, 255, 0, 128]);
+    const CLEAR: image::Rgba<u8> = image::Rgba([0, 0, 0, 0]);
+
+    /// The compressed image data of `image`, as it goes into `IDAT` chunks
+    fn image_data(image: &image::RgbaImage) -> Vec<u8> {
+        let mut png = Vec::new();
+        image::png::PNGEncoder::new(&mut png)
+            .encode(
+                image,
+                image.width(),
+                image.height(),
+                image::ColorType::RGBA(8),
+            )
+            .unwrap();
+
+        let mut rest = &png[PNG_SIGNATURE.len()..];
+        let mut data = Vec::new();
+        while !rest.is_empty() {
+            let length = u32::from_be_bytes([rest[0], rest[1], rest[2], rest[3]]) as usize;
+            if &rest[4..8] == b"IDAT" {
+                data.extend_from_slice(&rest[8..8 + length]);
+            }
+            rest = &rest[12 + length..];
+        }
+        data
+    }
+
+    /// An APNG of `width` × `height` with `frames`, each with its position,
+    /// dispose op and blend op, shown for a tenth of a second
+    fn apng(width: u32, height: u32, frames: &[(image::RgbaImage, u32, u32, u8, u8)]) -> Vec<u8> {
+        let mut png = PNG_SIGNATURE.to_vec();
+        let mut header = Vec::new();
+        header.extend_from_slice(&width.to_be_bytes());
+        header.extend_from_slice(&height.to_be_bytes());
+        header.extend_from_slice(&[8, 6, 0, 0, 0]);
+        write_chunk(&mut png, b"IHDR", &header);
+        let mut animation = (frames.len() as u32).to_be_bytes().to_vec();
+        animation.extend_from_slice(&0u32.to_be_bytes());
+        write_chunk(&mut png, b"acTL", &animation);
+
+        let mut sequence = 0u32;
+        for (idx, (image, x, y, dispose, blend)) in frames.iter().enumerate() {
+            let mut control = sequence.to_be_bytes().to_vec();
+            for number in &[image.width(), image.height(), *x, *y] {
+                control.extend_from_slice(&number.to_be_bytes());
+            }
+            control.extend_from_slice(&[0, 1, 0, 10, *dispose, *blend]);
+            write_chunk(&mut png, b"fcTL", &control);
+            sequence += 1;
+
+            if idx == 0 {
+                write_chunk(&mut png, b"IDAT", &image_data(image));
+            } else {
+                let mut data = sequence.to_be_bytes().to_vec();
+                data.extend(image_data(image));
+                write_chunk(&mut png, b"fdAT", &data);
+                sequence += 1;
+            }
+        }
+        write_chunk(&mut png, b"IEND", &[]);
+        png
+    }
+
+    fn compose(data: &[u8]) -> Vec<Vec<image::Rgba<u8>>> {
+        let apng = Apng::parse(data).unwrap().expect("Is animated");
+        apng.compose()
+            .unwrap()
+            .into_iter()
+            .map(|(image, delay)| {
+                assert_eq!(delay, Duration::from_millis(100));
+                image.pixels().copied().collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn apng_dispose_and_blend() {
+        // Half of the green pixel over the red one
+        let over_red = image::Rgba([127, 128, 0, 255]);
+        let table = [
+            (0, 0, [RED, GREEN]),
+            (0, BLEND_OVER, [RED, over_red]),
+            (DISPOSE_BACKGROUND, 0, [CLEAR, GREEN]),
+            (DISPOSE_BACKGROUND, BLEND_OVER, [CLEAR, GREEN]),
+            // There is nothing before the first frame to go back to
+            (DISPOSE_PREVIOUS, 0, [CLEAR, GREEN]),
+            (DISPOSE_PREVIOUS, BLEND_OVER, [CLEAR, GREEN]),
+        ];
+
+        for (dispose, blend, second) in table.iter() {
+            let data = apng(
+                2,
+                1,
+                &[
+                    (image::RgbaImage::from_pixel(2, 1, RED), 0, 0, *dispose, 0),
+                    (image::RgbaImage::from_pixel(1, 1, GREEN), 1, 0, 0, *blend),
+                ],
+            );
+            assert_eq!(
+                compose(&data),
+                vec![vec![RED, RED], second.to_vec()],
+                "dispose {}, blend {}",
+                dispose,
+                blend
+            );
+        }
+    }
+
+    #[test]
+    fn apng_dispose_previous() {
+        let data = apng(
+            2,
+            1,
+            &[
+                (image::RgbaImage::from_pixel(2, 1, RED), 0, 0, 0, 0),
+                (
+                    image::RgbaImage::from_pixel(1, 1, GREEN),
+                    1,
+                    0,
+                    DISPOSE_PREVIOUS,
+                    0,
+                ),
+                (image::RgbaImage::from_pixel(1, 1, GREEN), 0, 0, 0, 0),
+            ],
+        );
+        assert_eq!(
+            compose(&data),
+            vec![vec![RED, RED], vec![RED, GREEN], vec![GREEN, RED]]
+        );
+    }
+
+    #[test]
+    fn broken_apng() {
+        let data = apng(
+            2,
+            1,
+            &[
+                (image::RgbaImage::from_pixel(2, 1, RED), 0, 0, 0, 0),
+                (image::RgbaImage::from_pixel(1, 1, GREEN), 1, 0, 0, 0),
+            ],
+        );
+        assert!(Apng::parse(&data).is_ok());
+
+        for end in 0..data.len() {
+            assert!(Apng::parse(&data[..end]).is_err(), "Cut off at {}", end);
+        }
+        for idx in 0..data.len() {
+            let mut corrupted = data.clone();
+            corrupted[idx] ^= 1;
+            assert!(Apng::parse(&corrupted).is_err(), "Changed byte {}", idx);
+        }
+    }
+
+    #[test]
+    fn data_uris() {
+        let mut png = Vec::new();
+        image::png::PNGEncoder::new(&mut png)
+            .encode(
+                &[255, 0, 0, 255, 0, 0, 255, 255],
+                2,
+                1,
+                image::ColorType::RGBA(8),
+            )
+            .unwrap();
+        let uri = format!("data:image/png;base64,{}", base64::encode(&png));
+        assert_eq!(Picture::from_data_uri(&uri).unwrap().size(), (2., 1.));
+
+        let svg =
+            "%3Csvg%20xmlns=%22http://www.w3.org/2000/svg%22%20width=%224%22%20height=%223%22%3E\
+                   %3Crect%20width=%224%22%20height=%223%22/%3E%3C/svg%3E";
+        for header in &["data:image/svg+xml,", "data:image/svg+xml;charset=utf-8,"] {
+            let uri = format!("{}{}", header, svg);
+            assert_eq!(Picture::from_data_uri(&uri).unwrap().size(), (4., 3.));
+        }
+    }
+
+    #[test]
+    fn broken_data_uris() {
+        let table = [
+            "image/png;base64,AAAA",
+            "data:image/png;base64",
+            "data:text/plain;base64,aGVsbG8=",
+            "data:,hello",
+            "data:image/png;base64,not base64!",
+            "data:image/svg+xml,%3Csvg%zz",
+            "data:image/svg+xml,%3Csvg%2",
+        ];
+        for uri in table.iter() {
+            assert!(Picture::from_data_uri(uri).is_err(), "{}", uri);
+        }
+    }
+
+    #[test]
+    fn percent_decoding() {
+        assert_eq!(percent_decode("a%20b%2Fc%2f").unwrap(), b"a b/c/");
+        assert_eq!(percent_decode("100%25").unwrap(), b"100%");
+        assert!(percent_decode("%").is_err());
+        assert!(percent_decode("%+1").is_err());
+    }
+
+    #[test]
+    fn frame_at_wraps() {
+        let ms = Duration::from_millis;
+        let surface = || cairo::ImageSurface::create(cairo::Format::ARgb32, 1, 1).unwrap();
+        let start = Instant::now();
+        let frames = Frames {
+            frames: vec![(surface(), ms(100)), (surface(), ms(200))],
+            total: ms(300),
+            start,
+        };
+
+        let table = [
+            (0, 0, 100),
+            (50, 0, 100),
+            (100, 1, 300),
+            (299, 1, 300),
+            (300, 0, 400),
+            (650, 0, 700),
+            (1000, 1, 1200),
+        ];
+        for (elapsed, frame, next) in table.iter() {
+            assert_eq!(
+                frames.frame_at(start + ms(*elapsed)),
+                (*frame, start + ms(*next)),
+                "{}ms in",
+                elapsed
+            );
+        }
+    }
+}
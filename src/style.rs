@@ -0,0 +1,214 @@
+//! The flexbox style of an object, as taken by stretch but with every
+//! property optional
+
+use schemars::JsonSchema;
+use serde_derive::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, Default, Clone, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+#[serde(default)]
+// Only in the schema, so editors point out misspelled properties
+#[schemars(deny_unknown_fields)]
+pub struct Style {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schemars(schema_with = "schema::display")]
+    pub display: Option<stretch::style::Display>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schemars(schema_with = "schema::position_type")]
+    pub position_type: Option<stretch::style::PositionType>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schemars(schema_with = "schema::direction")]
+    pub direction: Option<stretch::style::Direction>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schemars(schema_with = "schema::flex_direction")]
+    pub flex_direction: Option<stretch::style::FlexDirection>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schemars(schema_with = "schema::flex_wrap")]
+    pub flex_wrap: Option<stretch::style::FlexWrap>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schemars(schema_with = "schema::overflow")]
+    pub overflow: Option<stretch::style::Overflow>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schemars(schema_with = "schema::align_items")]
+    pub align_items: Option<stretch::style::AlignItems>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schemars(schema_with = "schema::align_self")]
+    pub align_self: Option<stretch::style::AlignSelf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schemars(schema_with = "schema::align_content")]
+    pub align_content: Option<stretch::style::AlignContent>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schemars(schema_with = "schema::justify_content")]
+    pub justify_content: Option<stretch::style::JustifyContent>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schemars(schema_with = "schema::rect")]
+    pub position: Option<stretch::geometry::Rect<stretch::style::Dimension>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schemars(schema_with = "schema::rect")]
+    pub margin: Option<stretch::geometry::Rect<stretch::style::Dimension>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schemars(schema_with = "schema::rect")]
+    pub padding: Option<stretch::geometry::Rect<stretch::style::Dimension>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schemars(schema_with = "schema::rect")]
+    pub border: Option<stretch::geometry::Rect<stretch::style::Dimension>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub flex_grow: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub flex_shrink: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schemars(schema_with = "schema::dimension")]
+    pub flex_basis: Option<stretch::style::Dimension>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schemars(schema_with = "schema::size")]
+    pub size: Option<stretch::geometry::Size<stretch::style::Dimension>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schemars(schema_with = "schema::size")]
+    pub min_size: Option<stretch::geometry::Size<stretch::style::Dimension>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schemars(schema_with = "schema::size")]
+    pub max_size: Option<stretch::geometry::Size<stretch::style::Dimension>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schemars(schema_with = "schema::number")]
+    pub aspect_ratio: Option<stretch::number::Number>,
+}
+
+/// Schemas of the stretch types, which do not implement `JsonSchema`
+mod schema {
+    use schemars::gen::SchemaGenerator;
+    use schemars::schema::{InstanceType, Schema, SchemaObject, SubschemaValidation};
+
+    fn keywords(values: &[&str]) -> Schema {
+        SchemaObject {
+            instance_type: Some(InstanceType::String.into()),
+            enum_values: Some(values.iter().map(|&value| value.into()).collect()),
+            ..Default::default()
+        }
+        .into()
+    }
+
+    fn any_of(schemas: Vec<Schema>) -> Schema {
+        SchemaObject {
+            subschemas: Some(Box::new(SubschemaValidation {
+                any_of: Some(schemas),
+                ..Default::default()
+            })),
+            ..Default::default()
+        }
+        .into()
+    }
+
+    /// An object with exactly the given properties
+    fn properties(names: &[&str], schema: impl Fn() -> Schema) -> Schema {
+        let mut object = SchemaObject {
+            instance_type: Some(InstanceType::Object.into()),
+            ..Default::default()
+        };
+        for &name in names {
+            object
+                .object()
+                .properties
+                .insert(name.to_string(), schema());
+            object.object().required.insert(name.to_string());
+        }
+        object.object().additional_properties = Some(Box::new(Schema::Bool(false)));
+        object.into()
+    }
+
+    pub fn display(_: &mut SchemaGenerator) -> Schema {
+        keywords(&["flex", "none"])
+    }
+
+    pub fn position_type(_: &mut SchemaGenerator) -> Schema {
+        keywords(&["relative", "absolute"])
+    }
+
+    pub fn direction(_: &mut SchemaGenerator) -> Schema {
+        keywords(&["inherit", "lTR", "rTL"])
+    }
+
+    pub fn flex_direction(_: &mut SchemaGenerator) -> Schema {
+        keywords(&["row", "column", "rowReverse", "columnReverse"])
+    }
+
+    pub fn flex_wrap(_: &mut SchemaGenerator) -> Schema {
+        keywords(&["noWrap", "wrap", "wrapReverse"])
+    }
+
+    pub fn overflow(_: &mut SchemaGenerator) -> Schema {
+        keywords(&["visible", "hidden", "scroll"])
+    }
+
+    pub fn align_items(_: &mut SchemaGenerator) -> Schema {
+        keywords(&["flexStart", "flexEnd", "center", "baseline", "stretch"])
+    }
+
+    pub fn align_self(_: &mut SchemaGenerator) -> Schema {
+        keywords(&[
+            "auto",
+            "flexStart",
+            "flexEnd",
+            "center",
+            "baseline",
+            "stretch",
+        ])
+    }
+
+    pub fn align_content(_: &mut SchemaGenerator) -> Schema {
+        keywords(&[
+            "flexStart",
+            "flexEnd",
+            "center",
+            "stretch",
+            "spaceBetween",
+            "spaceAround",
+        ])
+    }
+
+    pub fn justify_content(_: &mut SchemaGenerator) -> Schema {
+        keywords(&[
+            "flexStart",
+            "flexEnd",
+            "center",
+            "spaceBetween",
+            "spaceAround",
+            "spaceEvenly",
+        ])
+    }
+
+    fn number_schema() -> Schema {
+        SchemaObject {
+            instance_type: Some(InstanceType::Number.into()),
+            ..Default::default()
+        }
+        .into()
+    }
+
+    /// `"auto"`, `{"points": 5}` or `{"percent": 0.5}`
+    fn dimension_schema() -> Schema {
+        any_of(vec![
+            keywords(&["auto", "undefined"]),
+            properties(&["points"], number_schema),
+            properties(&["percent"], number_schema),
+        ])
+    }
+
+    pub fn dimension(_: &mut SchemaGenerator) -> Schema {
+        dimension_schema()
+    }
+
+    pub fn rect(_: &mut SchemaGenerator) -> Schema {
+        properties(&["start", "end", "top", "bottom"], dimension_schema)
+    }
+
+    pub fn size(_: &mut SchemaGenerator) -> Schema {
+        properties(&["width", "height"], dimension_schema)
+    }
+
+    pub fn number(_: &mut SchemaGenerator) -> Schema {
+        any_of(vec![
+            keywords(&["undefined"]),
+            properties(&["defined"], number_schema),
+        ])
+    }
+}
@@ -0,0 +1,647 @@
+//! Stylesheets giving objects their style through selectors, like in CSS
+//!
+//! Selectors match objects by type (`Text`), class (`.tag`), id (`#clock`)
+//! and any combination of them (`Container.tag.focused`). Separated by spaces
+//! they match descendants (`.tag Text`), separated by commas any of them.
+//! Properties set inline on an object always win over the stylesheet, between
+//! rules the more specific selector wins and the later rule on a tie.
+
+use anyhow::{anyhow, Context};
+use schemars::JsonSchema;
+use serde_derive::{Deserialize, Serialize};
+
+use crate::color::Color;
+use crate::object::Object;
+use crate::style::Style;
+
+impl Style {
+    /// Take the fields that are not set in `self` from `other`
+    ///
+    /// Sizes and rects are merged side by side, an `undefined` side is taken
+    /// from `other`.
+    pub fn merge(&mut self, other: &Style) {
+        self.display = self.display.or(other.display);
+        self.position_type = self.position_type.or(other.position_type);
+        self.direction = self.direction.or(other.direction);
+        self.flex_direction = self.flex_direction.or(other.flex_direction);
+        self.flex_wrap = self.flex_wrap.or(other.flex_wrap);
+        self.overflow = self.overflow.or(other.overflow);
+        self.align_items = self.align_items.or(other.align_items);
+        self.align_self = self.align_self.or(other.align_self);
+        self.align_content = self.align_content.or(other.align_content);
+        self.justify_content = self.justify_content.or(other.justify_content);
+        self.position = merge_rect(self.position, other.position);
+        self.margin = merge_rect(self.margin, other.margin);
+        self.padding = merge_rect(self.padding, other.padding);
+        self.border = merge_rect(self.border, other.border);
+        self.flex_grow = self.flex_grow.or(other.flex_grow);
+        self.flex_shrink = self.flex_shrink.or(other.flex_shrink);
+        self.flex_basis = self.flex_basis.or(other.flex_basis);
+        self.size = merge_size(self.size, other.size);
+        self.min_size = merge_size(self.min_size, other.min_size);
+        self.max_size = merge_size(self.max_size, other.max_size);
+        self.aspect_ratio = self.aspect_ratio.or(other.aspect_ratio);
+    }
+
+    /// The full style, with the defaults of stretch where nothing is set
+    pub fn to_stretch(&self) -> stretch::style::Style {
+        let default = stretch::style::Style::default();
+        stretch::style::Style {
+            display: self.display.unwrap_or(default.display),
+            position_type: self.position_type.unwrap_or(default.position_type),
+            direction: self.direction.unwrap_or(default.direction),
+            flex_direction: self.flex_direction.unwrap_or(default.flex_direction),
+            flex_wrap: self.flex_wrap.unwrap_or(default.flex_wrap),
+            overflow: self.overflow.unwrap_or(default.overflow),
+            align_items: self.align_items.unwrap_or(default.align_items),
+            align_self: self.align_self.unwrap_or(default.align_self),
+            align_content: self.align_content.unwrap_or(default.align_content),
+            justify_content: self.justify_content.unwrap_or(default.justify_content),
+            position: self.position.unwrap_or(default.position),
+            margin: self.margin.unwrap_or(default.margin),
+            padding: self.padding.unwrap_or(default.padding),
+            border: self.border.unwrap_or(default.border),
+            flex_grow: self.flex_grow.unwrap_or(default.flex_grow),
+            flex_shrink: self.flex_shrink.unwrap_or(default.flex_shrink),
+            flex_basis: self.flex_basis.unwrap_or(default.flex_basis),
+            size: self.size.unwrap_or(default.size),
+            min_size: self.min_size.unwrap_or(default.min_size),
+            max_size: self.max_size.unwrap_or(default.max_size),
+            aspect_ratio: self.aspect_ratio.unwrap_or(default.aspect_ratio),
+        }
+    }
+}
+
+fn merge_dimension(
+    dimension: stretch::style::Dimension,
+    other: stretch::style::Dimension,
+) -> stretch::style::Dimension {
+    match dimension {
+        stretch::style::Dimension::Undefined => other,
+        _ => dimension,
+    }
+}
+
+fn merge_rect(
+    rect: Option<stretch::geometry::Rect<stretch::style::Dimension>>,
+    other: Option<stretch::geometry::Rect<stretch::style::Dimension>>,
+) -> Option<stretch::geometry::Rect<stretch::style::Dimension>> {
+    match (rect, other) {
+        (Some(rect), Some(other)) => Some(stretch::geometry::Rect {
+            start: merge_dimension(rect.start, other.start),
+            end: merge_dimension(rect.end, other.end),
+            top: merge_dimension(rect.top, other.top),
+            bottom: merge_dimension(rect.bottom, other.bottom),
+        }),
+        (rect, other) => rect.or(other),
+    }
+}
+
+fn merge_size(
+    size: Option<stretch::geometry::Size<stretch::style::Dimension>>,
+    other: Option<stretch::geometry::Size<stretch::style::Dimension>>,
+) -> Option<stretch::geometry::Size<stretch::style::Dimension>> {
+    match (size, other) {
+        (Some(size), Some(other)) => Some(stretch::geometry::Size {
+            width: merge_dimension(size.width, other.width),
+            height: merge_dimension(size.height, other.height),
+        }),
+        (size, other) => size.or(other),
+    }
+}
+
+/// What selectors are matched against
+struct Element {
+    kind: &'static str,
+    id: Option<String>,
+    classes: Vec<String>,
+}
+
+impl Element {
+    fn of<S>(object: &Object<S>) -> Element {
+        let attributes = object.get_attributes();
+        Element {
+            kind: object.get_type_name(),
+            id: attributes.id.clone(),
+            classes: attributes.class.clone(),
+        }
+    }
+}
+
+/// A selector for a single object, like `Text.tag#clock`
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Compound {
+    kind: Option<String>,
+    id: Option<String>,
+    classes: Vec<String>,
+}
+
+impl Compound {
+    fn parse(input: &str) -> anyhow::Result<Compound> {
+        fn is_name_char(c: char) -> bool {
+            c.is_alphanumeric() || c == '-' || c == '_'
+        }
+
+        let mut compound = Compound::default();
+        let mut rest = input;
+
+        let kind_end = rest
+            .find(|c: char| c == '.' || c == '#')
+            .unwrap_or(rest.len());
+        match &rest[..kind_end] {
+            "" | "*" => {}
+            kind if kind.chars().all(is_name_char) => compound.kind = Some(kind.to_string()),
+            kind => return Err(anyhow!("{} is not an object type", kind)),
+        }
+        rest = &rest[kind_end..];
+
+        while let Some(marker) = rest.chars().next() {
+            if marker != '.' && marker != '#' {
+                return Err(anyhow!("Unexpected '{}' in {}", marker, input));
+            }
+
+            let name_end = rest[1..]
+                .find(|c: char| !is_name_char(c))
+                .map_or(rest.len(), |end| end + 1);
+            let name = &rest[1..name_end];
+            if name.is_empty() {
+                return Err(anyhow!("Expected a name after '{}' in {}", marker, input));
+            }
+
+            if marker == '.' {
+                compound.classes.push(name.to_string());
+            } else {
+                compound.id = Some(name.to_string());
+            }
+            rest = &rest[name_end..];
+        }
+
+        Ok(compound)
+    }
+
+    fn matches(&self, element: &Element) -> bool {
+        self.kind
+            .as_ref()
+            .map_or(true, |kind| kind.eq_ignore_ascii_case(element.kind))
+            && self
+                .id
+                .as_ref()
+                .map_or(true, |id| element.id.as_ref() == Some(id))
+            && self
+                .classes
+                .iter()
+                .all(|class| element.classes.contains(class))
+    }
+}
+
+impl std::fmt::Display for Compound {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match &self.kind {
+            Some(kind) => write!(f, "{}", kind)?,
+            None if self.id.is_none() && self.classes.is_empty() => write!(f, "*")?,
+            None => {}
+        }
+        for class in &self.classes {
+            write!(f, ".{}", class)?;
+        }
+        if let Some(id) = &self.id {
+            write!(f, "#{}", id)?;
+        }
+        Ok(())
+    }
+}
+
+/// A selector like `Container.tag Text`, every part matching an ancestor of
+/// the object the last part matches
+#[derive(Debug, Clone, PartialEq)]
+pub struct Selector {
+    parts: Vec<Compound>,
+}
+
+impl Selector {
+    /// Ids, classes and types in the selector, compared in this order
+    fn specificity(&self) -> (usize, usize, usize) {
+        self.parts
+            .iter()
+            .fold((0, 0, 0), |(ids, classes, kinds), part| {
+                (
+                    ids + part.id.iter().count(),
+                    classes + part.classes.len(),
+                    kinds + part.kind.iter().count(),
+                )
+            })
+    }
+
+    /// `ancestors` go from the root down to the parent of `element`
+    fn matches(&self, element: &Element, ancestors: &[Element]) -> bool {
+        let (last, rest) = match self.parts.split_last() {
+            Some(parts) => parts,
+            None => return false,
+        };
+        if !last.matches(element) {
+            return false;
+        }
+
+        let mut ancestors = ancestors.iter().rev();
+        rest.iter()
+            .rev()
+            .all(|part| ancestors.any(|ancestor| part.matches(ancestor)))
+    }
+}
+
+impl std::str::FromStr for Selector {
+    type Err = anyhow::Error;
+
+    fn from_str(input: &str) -> anyhow::Result<Selector> {
+        let parts = input
+            .split_whitespace()
+            .map(Compound::parse)
+            .collect::<anyhow::Result<Vec<_>>>()
+            .with_context(|| format!("{} is not a valid selector", input))?;
+
+        if parts.is_empty() {
+            return Err(anyhow!("A selector can not be empty"));
+        }
+
+        Ok(Selector { parts })
+    }
+}
+
+impl std::fmt::Display for Selector {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        for (index, part) in self.parts.iter().enumerate() {
+            if index > 0 {
+                write!(f, " ")?;
+            }
+            write!(f, "{}", part)?;
+        }
+        Ok(())
+    }
+}
+
+/// Selectors separated by commas, any of which can match
+mod selector_list {
+    use super::Selector;
+    use serde::de::Error as _;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S>(value: &[Selector], serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let selectors: Vec<String> = value.iter().map(Selector::to_string).collect();
+        serializer.serialize_str(&selectors.join(", "))
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Vec<Selector>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let list = String::deserialize(deserializer)?;
+        list.split(',')
+            .map(|selector| selector.parse().map_err(D::Error::custom))
+            .collect()
+    }
+}
+
+/// What a stylesheet can set on an object, for the kinds of objects it applies
+/// to
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct Properties {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub style: Option<Style>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub background: Option<Color>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub corner_radius: Option<f64>,
+    /// The text color or tint of an image, containers pass it down to their
+    /// texts
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub color: Option<Color>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub font: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub font_size: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub opacity: Option<f64>,
+}
+
+impl Properties {
+    /// Set the properties `object` does not have yet
+    fn fill(&self, object: &mut Object) {
+        fn fill<T: Clone>(field: &mut Option<T>, value: &Option<T>) {
+            if field.is_none() {
+                *field = value.clone();
+            }
+        }
+
+        if let Some(style) = &self.style {
+            object.get_style_mut().merge(style);
+        }
+        fill(&mut object.get_attributes_mut().opacity, &self.opacity);
+
+        match object {
+            Object::Container {
+                background,
+                corner_radius,
+                color,
+                font,
+                font_size,
+                ..
+            } => {
+                fill(background, &self.background);
+                fill(corner_radius, &self.corner_radius);
+                fill(color, &self.color);
+                fill(font, &self.font);
+                fill(font_size, &self.font_size);
+            }
+            Object::Text {
+                color,
+                font,
+                font_size,
+                ..
+            } => {
+                fill(color, &self.color);
+                fill(font, &self.font);
+                fill(font_size, &self.font_size);
+            }
+            Object::Image { tint, .. } => fill(tint, &self.color),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct Rule {
+    /// Selectors separated by commas
+    #[serde(with = "selector_list")]
+    #[schemars(with = "String")]
+    pub selector: Vec<Selector>,
+    #[serde(flatten)]
+    pub properties: Properties,
+}
+
+/// Rules applied to the objects before they are drawn
+///
+/// Given as a `{"stylesheet": [...]}` message or loaded from a JSON file with
+/// `--stylesheet`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(transparent)]
+pub struct Stylesheet {
+    rules: Vec<Rule>,
+}
+
+impl Stylesheet {
+    pub fn load(path: &str) -> anyhow::Result<Stylesheet> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Could not read stylesheet {}", path))?;
+        serde_json::from_str(&content)
+            .with_context(|| format!("Could not parse stylesheet {}", path))
+    }
+
+    pub fn new(rules: Vec<Rule>) -> Stylesheet {
+        Stylesheet { rules }
+    }
+
+    /// Set what the rules give to every object and its children
+    pub fn apply(&self, objects: &mut [Object]) {
+        if !self.rules.is_empty() {
+            self.apply_to(objects, &mut vec![]);
+        }
+    }
+
+    fn apply_to(&self, objects: &mut [Object], ancestors: &mut Vec<Element>) {
+        for object in objects {
+            let element = Element::of(object);
+
+            let mut matching: Vec<_> = self
+                .rules
+                .iter()
+                .enumerate()
+                .filter_map(|(index, rule)| {
+                    rule.selector
+                        .iter()
+                        .filter(|selector| selector.matches(&element, ancestors))
+                        .map(Selector::specificity)
+                        .max()
+                        .map(|specificity| (specificity, index, &rule.properties))
+                })
+                .collect();
+
+            // The most important rule fills the object first
+            matching.sort_by(|a, b| (b.0, b.1).cmp(&(a.0, a.1)));
+            for (_, _, properties) in matching {
+                properties.fill(object);
+            }
+
+            if let Object::Container { children, .. } = object {
+                ancestors.push(element);
+                self.apply_to(children, ancestors);
+                ancestors.pop();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn element(kind: &'static str, id: Option<&str>, classes: &[&str]) -> Element {
+        Element {
+            kind,
+            id: id.map(String::from),
+            classes: classes.iter().map(|class| class.to_string()).collect(),
+        }
+    }
+
+    /// `objects` after applying a stylesheet of `rules` to them
+    fn apply(rules: serde_json::Value, objects: serde_json::Value) -> serde_json::Value {
+        let stylesheet: Stylesheet = serde_json::from_value(rules).unwrap();
+        let mut objects: Vec<Object> = serde_json::from_value(objects).unwrap();
+        stylesheet.apply(&mut objects);
+        serde_json::to_value(&objects).unwrap()
+    }
+
+    #[test]
+    fn parse_selectors() {
+        let table = [
+            ("Text", "Text"),
+            (".tag", ".tag"),
+            ("#clock", "#clock"),
+            ("Container.tag.focused", "Container.tag.focused"),
+            ("Text#clock.tag", "Text.tag#clock"),
+            (".tag Text", ".tag Text"),
+            ("  Container   .tag  Text ", "Container .tag Text"),
+            ("*", "*"),
+        ];
+        for (input, expected) in table.iter() {
+            let selector: Selector = input.parse().unwrap();
+            assert_eq!(selector.to_string(), *expected);
+        }
+
+        for input in &["", " ", "Text..tag", "Text.", "#", "Te$t", "Text > Text"] {
+            assert!(input.parse::<Selector>().is_err(), "{:?} parsed", input);
+        }
+    }
+
+    #[test]
+    fn selector_lists() {
+        let rule: Rule = serde_json::from_value(json!({
+            "selector": "Text, .tag Text ,#clock",
+            "font": "mono",
+        }))
+        .unwrap();
+        let expected: Vec<Selector> = vec![
+            "Text".parse().unwrap(),
+            ".tag Text".parse().unwrap(),
+            "#clock".parse().unwrap(),
+        ];
+        assert_eq!(rule.selector, expected);
+
+        assert!(serde_json::from_value::<Rule>(json!({"selector": "Text,"})).is_err());
+    }
+
+    #[test]
+    fn match_selectors() {
+        let root = element("Container", None, &[]);
+        let tag = element("Container", None, &["tag", "focused"]);
+        let clock = element("Text", Some("clock"), &["tag"]);
+        let ancestors = [root, tag];
+
+        let table = [
+            ("Text", true),
+            ("text", true),
+            ("Container", false),
+            (".tag", true),
+            (".focused", false),
+            ("#clock", true),
+            ("#date", false),
+            ("Text.tag#clock", true),
+            ("*", true),
+            (".tag Text", true),
+            (".focused Text", true),
+            ("Container .focused Text", true),
+            (".focused Container Text", false),
+            (".urgent Text", false),
+            ("Text Text", false),
+        ];
+        for (selector, expected) in table.iter() {
+            let selector: Selector = selector.parse().unwrap();
+            assert_eq!(
+                selector.matches(&clock, &ancestors),
+                *expected,
+                "{}",
+                selector
+            );
+        }
+
+        let selector: Selector = "Container.tag.focused".parse().unwrap();
+        assert!(selector.matches(&ancestors[1], &ancestors[..1]));
+        assert!(!selector.matches(&ancestors[0], &[]));
+    }
+
+    #[test]
+    fn cascade() {
+        let objects = json!([{
+            "type": "Container",
+            "class": ["tag"],
+            "style": {},
+            "children": [{
+                "type": "Text",
+                "id": "clock",
+                "class": ["tag"],
+                "text": "12:00",
+                "style": {},
+            }],
+        }]);
+
+        let table = [
+            // The later of two equally specific rules wins
+            (
+                json!([{"selector": "Text", "font": "a"}, {"selector": "Text", "font": "b"}]),
+                "b",
+            ),
+            // The more specific one wins over a later one
+            (
+                json!([{"selector": ".tag Text", "font": "a"}, {"selector": "Text", "font": "b"}]),
+                "a",
+            ),
+            (
+                json!([{"selector": "#clock", "font": "a"}, {"selector": ".tag Text.tag", "font": "b"}]),
+                "a",
+            ),
+            // The most specific selector of a list counts
+            (
+                json!([{"selector": "Text, #clock", "font": "a"}, {"selector": ".tag", "font": "b"}]),
+                "a",
+            ),
+            // Only matching rules apply
+            (
+                json!([{"selector": "#clock", "font": "a"}, {"selector": "#date", "font": "b"}]),
+                "a",
+            ),
+        ];
+        for (rules, font) in table.iter() {
+            let objects = apply(rules.clone(), objects.clone());
+            assert_eq!(objects[0]["children"][0]["font"], *font, "{}", rules);
+        }
+    }
+
+    #[test]
+    fn fill_keeps_what_is_set() {
+        let rules = json!([{
+            "selector": "*",
+            "style": {"flexGrow": 2., "flexShrink": 3.},
+            "background": "$bg",
+            "color": "$fg",
+            "font": "sans",
+            "font_size": 10.,
+            "opacity": 0.5,
+        }]);
+        let objects = apply(
+            rules,
+            json!([
+                {
+                    "type": "Container",
+                    "style": {},
+                    "children": [],
+                },
+                {
+                    "type": "Text",
+                    "text": "12:00",
+                    "font": "mono",
+                    "style": {"flexGrow": 1.},
+                },
+                {
+                    "type": "Image",
+                    "path": "icon.png",
+                    "opacity": 1.,
+                    "style": {},
+                },
+            ]),
+        );
+
+        assert_eq!(objects[0]["background"], "$bg");
+        assert_eq!(objects[0]["color"], "$fg");
+        assert_eq!(
+            objects[0]["style"],
+            json!({"flexGrow": 2., "flexShrink": 3.})
+        );
+
+        // What the object sets itself wins over the stylesheet
+        assert_eq!(objects[1]["font"], "mono");
+        assert_eq!(objects[1]["font_size"], 10.);
+        assert_eq!(objects[1]["color"], "$fg");
+        assert_eq!(objects[1]["opacity"], 0.5);
+        assert_eq!(
+            objects[1]["style"],
+            json!({"flexGrow": 1., "flexShrink": 3.})
+        );
+        assert!(objects[1].get("background").is_none());
+
+        // The color of an image is its tint
+        assert_eq!(objects[2]["tint"], "$fg");
+        assert_eq!(objects[2]["opacity"], 1.);
+    }
+}
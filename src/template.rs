@@ -0,0 +1,218 @@
+use schemars::JsonSchema;
+use serde_derive::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+
+use crate::check::Checker;
+use crate::object::Object;
+
+/// A named tree of objects with `{{placeholders}}`, registered once and then
+/// rendered with only the data that changes
+///
+/// Given as a `{"template": {"name": "battery", "objects": [...]}}` message.
+/// A string that is just a placeholder, like `"{{pct}}"`, is replaced by the
+/// data value itself so numbers stay numbers, placeholders inside longer
+/// strings are replaced by its text.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct Template {
+    pub name: String,
+    /// The objects as they are sent, they are only checked once rendered
+    pub objects: Vec<Value>,
+}
+
+/// Draw a registered template, written as
+/// `{"render": "battery", "data": {"pct": 0.56, "color": "#0f0"}}`
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct Render {
+    #[serde(rename = "render")]
+    pub template: String,
+    #[serde(default)]
+    pub data: Map<String, Value>,
+    /// Fill this slot with the objects instead of replacing all of them
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub slot: Option<String>,
+    /// Seconds until the window is hidden, instead of those of `--timeout`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<f64>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Templates {
+    templates: HashMap<String, Template>,
+}
+
+impl Templates {
+    /// Register a template, replacing one of the same name
+    pub fn insert(&mut self, template: Template) {
+        self.templates.insert(template.name.clone(), template);
+    }
+
+    /// The objects of the template with the data filled in, checked by
+    /// `checker` like incoming ones
+    pub fn render(&self, render: &Render, checker: &Checker) -> anyhow::Result<Vec<Object>> {
+        use anyhow::Context;
+
+        let template = self
+            .templates
+            .get(&render.template)
+            .with_context(|| format!("The template {} is not defined", render.template))?;
+
+        template
+            .objects
+            .iter()
+            .map(|object| fill(object, &render.data))
+            .collect::<anyhow::Result<_>>()
+            .and_then(|objects| checker.decode(Value::Array(objects)))
+            .with_context(|| format!("Could not render the template {}", render.template))
+    }
+}
+
+/// The data a placeholder refers to, `a.b` looks into nested objects
+fn lookup<'a>(data: &'a Map<String, Value>, name: &str) -> anyhow::Result<&'a Value> {
+    let mut parts = name.split('.');
+    let mut value = parts.next().and_then(|first| data.get(first));
+    for part in parts {
+        value = value.and_then(|value| value.get(part));
+    }
+
+    value.ok_or_else(|| anyhow::anyhow!("No data given for {{{{{}}}}}", name))
+}
+
+fn fill(value: &Value, data: &Map<String, Value>) -> anyhow::Result<Value> {
+    Ok(match value {
+        Value::String(text) => {
+            let trimmed = text.trim();
+            match trimmed
+                .strip_prefix("{{")
+                .and_then(|rest| rest.strip_suffix("}}"))
+            {
+                Some(name) if !name.contains("{{") => lookup(data, name.trim())?.clone(),
+                _ => Value::String(interpolate(text, data)?),
+            }
+        }
+        Value::Array(values) => Value::Array(
+            values
+                .iter()
+                .map(|value| fill(value, data))
+                .collect::<anyhow::Result<_>>()?,
+        ),
+        Value::Object(values) => Value::Object(
+            values
+                .iter()
+                .map(|(key, value)| Ok((key.clone(), fill(value, data)?)))
+                .collect::<anyhow::Result<_>>()?,
+        ),
+        value => value.clone(),
+    })
+}
+
+fn interpolate(text: &str, data: &Map<String, Value>) -> anyhow::Result<String> {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find("{{") {
+        let end = rest[start..]
+            .find("}}")
+            .ok_or_else(|| anyhow::anyhow!("Unclosed placeholder in {:?}", text))?;
+
+        result.push_str(&rest[..start]);
+        match lookup(data, rest[start + 2..start + end].trim())? {
+            Value::String(value) => result.push_str(value),
+            value => result.push_str(&value.to_string()),
+        }
+        rest = &rest[start + end + 2..];
+    }
+    result.push_str(rest);
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn data() -> Map<String, Value> {
+        match json!({
+            "pct": 0.56,
+            "name": "BAT0",
+            "battery": {"state": "charging", "level": 3},
+        }) {
+            Value::Object(data) => data,
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn placeholder_keeps_type() {
+        let table = [
+            ("{{pct}}", json!(0.56)),
+            (" {{ pct }} ", json!(0.56)),
+            ("{{name}}", json!("BAT0")),
+            ("{{battery.level}}", json!(3)),
+            ("{{battery}}", json!({"state": "charging", "level": 3})),
+        ];
+        for (template, expected) in table.iter() {
+            assert_eq!(fill(&json!(template), &data()).unwrap(), *expected);
+        }
+    }
+
+    #[test]
+    fn interpolate_in_text() {
+        let table = [
+            ("{{pct}}%", "0.56%"),
+            (
+                "Battery {{name}}: {{ battery.state }}",
+                "Battery BAT0: charging",
+            ),
+            ("{{name}}{{battery.level}}", "BAT03"),
+            ("{{name}} and {{pct}}", "BAT0 and 0.56"),
+            ("No placeholders", "No placeholders"),
+        ];
+        for (template, expected) in table.iter() {
+            assert_eq!(interpolate(template, &data()).unwrap(), *expected);
+            assert_eq!(fill(&json!(template), &data()).unwrap(), json!(expected));
+        }
+    }
+
+    #[test]
+    fn fill_objects() {
+        let objects = json!([{
+            "type": "Text",
+            "text": "{{pct}}%",
+            "value": "{{pct}}",
+            "font_size": 12,
+            "class": ["{{battery.state}}"],
+        }]);
+        assert_eq!(
+            fill(&objects, &data()).unwrap(),
+            json!([{
+                "type": "Text",
+                "text": "0.56%",
+                "value": 0.56,
+                "font_size": 12,
+                "class": ["charging"],
+            }])
+        );
+    }
+
+    #[test]
+    fn missing_data() {
+        let table = [
+            "{{nope}}",
+            "Battery {{nope}}",
+            "{{battery.nope}}",
+            "{{name.length}}",
+            "{{pct",
+            "Battery {{pct",
+        ];
+        for template in table.iter() {
+            assert!(fill(&json!(template), &data()).is_err(), "{}", template);
+        }
+
+        let error = fill(&json!("{{nope}}"), &data()).unwrap_err();
+        assert_eq!(error.to_string(), "No data given for {{nope}}");
+        assert!(lookup(&data(), "battery.nope").is_err());
+        assert_eq!(lookup(&data(), "battery.state").unwrap(), "charging");
+    }
+}
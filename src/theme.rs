@@ -0,0 +1,49 @@
+use schemars::JsonSchema;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::color::Color;
+use crate::object::ColorDef;
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+struct ThemeColor(
+    #[serde(with = "ColorDef")]
+    #[schemars(with = "ColorDef")]
+    piet::Color,
+);
+
+/// Named colors objects can refer to as `$name`
+///
+/// Given as a `{"theme": {"accent": "#9fbc00"}}` message or loaded from a
+/// TOML file of the same shape with `--theme`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(transparent)]
+pub struct Theme {
+    colors: HashMap<String, ThemeColor>,
+}
+
+impl Theme {
+    pub fn load(path: &str) -> anyhow::Result<Theme> {
+        use anyhow::Context;
+
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Could not read theme {}", path))?;
+        toml::from_str(&content).with_context(|| format!("Could not parse theme {}", path))
+    }
+
+    pub fn insert(&mut self, name: &str, color: piet::Color) {
+        self.colors.insert(name.to_string(), ThemeColor(color));
+    }
+
+    pub fn get(&self, name: &str) -> Option<&piet::Color> {
+        self.colors.get(name).map(|ThemeColor(color)| color)
+    }
+
+    /// The actual color, `None` if it refers to a name not in the theme
+    pub fn resolve(&self, color: &Color) -> Option<piet::Color> {
+        match color {
+            Color::Value(color) => Some(color.clone()),
+            Color::Var(name) => self.get(name).cloned(),
+        }
+    }
+}
@@ -0,0 +1,69 @@
+use futures::stream::Stream;
+use std::cell::Cell;
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::task::{Context, Poll};
+use std::time::Instant;
+
+/// A stream that fires once the deadline set through its `TimerHandle` is
+/// reached
+///
+/// Setting a deadline does not wake the stream, it is meant to be set from
+/// within the same event loop that polls it.
+pub struct Timer {
+    deadline: Rc<Cell<Option<Instant>>>,
+    delay: Option<tokio::time::Delay>,
+}
+
+#[derive(Clone)]
+pub struct TimerHandle(Rc<Cell<Option<Instant>>>);
+
+impl TimerHandle {
+    /// Set the next deadline, `None` disarms the timer
+    pub fn set(&self, deadline: Option<Instant>) {
+        self.0.set(deadline);
+    }
+}
+
+impl Timer {
+    pub fn new() -> (Timer, TimerHandle) {
+        let deadline = Rc::new(Cell::new(None));
+        (
+            Timer {
+                deadline: deadline.clone(),
+                delay: None,
+            },
+            TimerHandle(deadline),
+        )
+    }
+}
+
+impl Stream for Timer {
+    type Item = Instant;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        let deadline = match self.deadline.get() {
+            Some(deadline) => tokio::time::Instant::from_std(deadline),
+            None => {
+                self.delay = None;
+                return Poll::Pending;
+            }
+        };
+
+        match &mut self.delay {
+            Some(delay) if delay.deadline() == deadline => {}
+            Some(delay) => delay.reset(deadline),
+            None => self.delay = Some(tokio::time::delay_until(deadline)),
+        }
+
+        let delay = self.delay.as_mut().expect("Delay was just set");
+        if let Poll::Pending = Pin::new(delay).poll(cx) {
+            return Poll::Pending;
+        }
+
+        self.delay = None;
+        self.deadline.set(None);
+        Poll::Ready(Some(Instant::now()))
+    }
+}
@@ -4,16 +4,25 @@ use futures::stream::Stream;
 use mio::unix::EventedFd;
 use mio::{PollOpt, Ready, Token};
 use piet::RenderContext;
+use std::collections::{HashMap, HashSet};
 use std::os::unix::io::RawFd;
 use std::pin::Pin;
 use std::rc::Rc;
 use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
 use stretch::Stretch;
 use xcb_util::ewmh;
 // The following two functions are from https://github.com/mjkillough/cnx/blob/master/src/bar.rs
 
+use crate::animation::{self, Progress};
+use crate::color::Color;
 use crate::draw;
-use crate::object::Object;
+use crate::object::{
+    self, Inherited, MarqueeMode, Object, Overflow, Transition, TransitionProperty,
+};
+use crate::picture::Picture;
+use crate::stylesheet::Stylesheet;
+use crate::theme::Theme;
 
 fn get_root_visual_type(conn: &xcb::Connection, screen: &xcb::Screen<'_>) -> xcb::Visualtype {
     for root in conn.get_setup().roots() {
@@ -51,7 +60,10 @@ fn cairo_surface_for_xcb_window(
 struct NodeObject {
     node: stretch::node::Node,
     object: Option<Object>,
+    picture: Option<Rc<Picture>>,
     children: Vec<NodeObject>,
+    /// The id of the object, or its position in the tree
+    key: String,
 }
 
 impl NodeObject {
@@ -59,7 +71,9 @@ impl NodeObject {
         NodeObject {
             node,
             object,
+            picture: None,
             children: vec![],
+            key: String::new(),
         }
     }
 
@@ -68,12 +82,191 @@ impl NodeObject {
     }
 }
 
+/// A laid out tree of objects, kept to redraw parts of it
+struct Scene {
+    stretch: Stretch,
+    root: NodeObject,
+    /// The animated pictures drawn in the last full paint
+    animations: Vec<AnimatedPicture>,
+    /// The scrolling texts drawn in the last full paint
+    marquees: Vec<ScrollingText>,
+    /// How the objects with an id were drawn in the last full paint
+    states: HashMap<String, NodeState>,
+    /// Whether a keyframe animation was running in the last full paint
+    animating: bool,
+}
+
+/// The properties of an object that can be transitioned
+#[derive(Debug, Clone)]
+struct NodeState {
+    /// In window coordinates
+    area: kurbo::Rect,
+    background: Option<piet::Color>,
+    color: Option<piet::Color>,
+    opacity: f64,
+}
+
+impl NodeState {
+    fn same_as(&self, other: &NodeState) -> bool {
+        let same_color = |a: &Option<piet::Color>, b: &Option<piet::Color>| {
+            a.as_ref().map(piet::Color::as_rgba_u32) == b.as_ref().map(piet::Color::as_rgba_u32)
+        };
+        self.area == other.area
+            && same_color(&self.background, &other.background)
+            && same_color(&self.color, &other.color)
+            && self.opacity == other.opacity
+    }
+}
+
+struct ActiveTransition {
+    from: NodeState,
+    to: NodeState,
+    start: Instant,
+    transition: Transition,
+}
+
+impl ActiveTransition {
+    fn is_finished(&self, now: Instant) -> bool {
+        now.saturating_duration_since(self.start) >= Duration::from_millis(self.transition.duration)
+    }
+
+    /// The state to draw at `now`, `target` is where the object is laid out now
+    fn state_at(&self, target: &NodeState, now: Instant) -> NodeState {
+        let elapsed = now.saturating_duration_since(self.start).as_secs_f64();
+        let duration = Duration::from_millis(self.transition.duration).as_secs_f64();
+        let t = if duration > 0. {
+            self.transition.easing.apply(elapsed / duration)
+        } else {
+            1.
+        };
+        let animates = |property| self.transition.properties.contains(&property);
+
+        NodeState {
+            area: if animates(TransitionProperty::Layout) {
+                animation::lerp_rect(&self.from.area, &target.area, t)
+            } else {
+                target.area
+            },
+            background: if animates(TransitionProperty::Background) {
+                animation::lerp_opt_color(
+                    self.from.background.as_ref(),
+                    target.background.as_ref(),
+                    t,
+                )
+            } else {
+                target.background.clone()
+            },
+            color: if animates(TransitionProperty::Color) {
+                animation::lerp_opt_color(self.from.color.as_ref(), target.color.as_ref(), t)
+            } else {
+                target.color.clone()
+            },
+            opacity: if animates(TransitionProperty::Opacity) {
+                animation::lerp(self.from.opacity, target.opacity, t)
+            } else {
+                target.opacity
+            },
+        }
+    }
+}
+
+struct AnimatedPicture {
+    picture: Rc<Picture>,
+    /// Where the picture is drawn, in window coordinates
+    area: kurbo::Rect,
+    /// The frame that was drawn last
+    frame: usize,
+}
+
+/// A text scrolled by its marquee
+struct ScrollingText {
+    marquee: object::Marquee,
+    start: Instant,
+    text_width: f64,
+    box_width: f64,
+    /// Where the text is drawn, in window coordinates
+    area: kurbo::Rect,
+    /// How far it was scrolled when drawn last
+    offset: f64,
+}
+
+impl ScrollingText {
+    fn offset_at(&self, now: Instant) -> f64 {
+        self.marquee.offset(
+            now.saturating_duration_since(self.start),
+            self.text_width,
+            self.box_width,
+        )
+    }
+
+    /// When the text has to be drawn again, `None` if it stopped for good
+    fn next_frame(&self, now: Instant) -> Option<Instant> {
+        let elapsed = now.saturating_duration_since(self.start);
+        self.marquee
+            .until_moving(elapsed, self.text_width, self.box_width)
+            .map(|wait| now + wait.max(animation::FRAME_INTERVAL))
+    }
+}
+
+/// State shared while painting the nodes of a scene
+struct Painter<'a> {
+    now: Instant,
+    /// The raw context, for what piet does not expose
+    cairo: cairo::Context,
+    /// Only nodes overlapping these areas are painted, all of them if `None`
+    damage: Option<&'a [kurbo::Rect]>,
+    animations: Vec<AnimatedPicture>,
+    marquees: Vec<ScrollingText>,
+    /// How the objects with an id were drawn before the scene changed, only
+    /// set for the first paint of a new scene
+    previous: Option<&'a HashMap<String, NodeState>>,
+    transitions: &'a mut HashMap<String, ActiveTransition>,
+    states: HashMap<String, NodeState>,
+    /// When the keyframe animations started, by the key of their object
+    animation_starts: &'a mut HashMap<String, Instant>,
+    /// The keys of the objects with an animation that were painted
+    animated: HashSet<String>,
+    /// Whether any keyframe animation has not finished yet
+    animating: bool,
+    theme: &'a Theme,
+}
+
+fn overlaps(a: &kurbo::Rect, b: &kurbo::Rect) -> bool {
+    a.x0 < b.x1 && b.x0 < a.x1 && a.y0 < b.y1 && b.y0 < a.y1
+}
+
+/// Where `area` ends up once moved by `translate` and scaled by `scale` around
+/// its center
+fn transform_area(area: &kurbo::Rect, translate: kurbo::Vec2, scale: f64) -> kurbo::Rect {
+    let half_width = area.width() * scale.abs() / 2.;
+    let half_height = area.height() * scale.abs() / 2.;
+    let center_x = (area.x0 + area.x1) / 2. + translate.x;
+    let center_y = (area.y0 + area.y1) / 2. + translate.y;
+    kurbo::Rect::new(
+        center_x - half_width,
+        center_y - half_height,
+        center_x + half_width,
+        center_y + half_height,
+    )
+}
+
 pub struct Window {
     ewmh_connection: Rc<ewmh::Connection>,
     window: u32,
+    surface: cairo::XCBSurface,
     context: cairo::Context,
+    /// Keeps `paint` from mapping the window again
+    hidden: bool,
     width: u16,
     height: u16,
+    scene: Option<Scene>,
+    transitions: HashMap<String, ActiveTransition>,
+    animation_starts: HashMap<String, Instant>,
+    theme: Theme,
+    /// The names of the colors missing from the theme that were warned about
+    missing_colors: HashSet<String>,
+    stylesheet: Stylesheet,
+    text_defaults: Inherited,
 }
 
 impl Window {
@@ -122,16 +315,105 @@ impl Window {
         Ok(Window {
             ewmh_connection: Rc::new(ewmh_connection),
             window,
+            surface,
             context,
+            hidden: false,
             width,
             height,
+            scene: None,
+            transitions: HashMap::new(),
+            animation_starts: HashMap::new(),
+            theme: Theme::default(),
+            missing_colors: HashSet::new(),
+            stylesheet: Stylesheet::default(),
+            text_defaults: Inherited::default(),
         })
     }
 
-    pub fn draw(&mut self, root_objects: Vec<Object>) -> anyhow::Result<()> {
-        self.context.push_group();
-        let mut crc = piet_cairo::CairoRenderContext::new(&mut self.context);
-        crc.clear(piet::Color::WHITE);
+    /// Replace the theme, takes effect with the next `draw`
+    pub fn set_theme(&mut self, theme: Theme) {
+        self.theme = theme;
+        self.missing_colors.clear();
+    }
+
+    /// Replace the stylesheet, takes effect with the next `draw`
+    pub fn set_stylesheet(&mut self, stylesheet: Stylesheet) {
+        self.stylesheet = stylesheet;
+    }
+
+    /// Replace the text properties inherited by the objects at the root,
+    /// takes effect with the next `draw`
+    pub fn set_text_defaults(&mut self, text_defaults: Inherited) {
+        self.text_defaults = text_defaults;
+    }
+
+    /// Unmap the window until `show` is called
+    pub fn hide(&mut self) -> anyhow::Result<()> {
+        self.hidden = true;
+        xcb::unmap_window(&self.ewmh_connection, self.window).request_check()?;
+        self.ewmh_connection.flush();
+        Ok(())
+    }
+
+    pub fn show(&mut self) -> anyhow::Result<()> {
+        self.hidden = false;
+        xcb::map_window(&self.ewmh_connection, self.window).request_check()?;
+        self.ewmh_connection.flush();
+        Ok(())
+    }
+
+    pub fn move_to(&mut self, x: i16, y: i16) -> anyhow::Result<()> {
+        xcb::configure_window(
+            &self.ewmh_connection,
+            self.window,
+            &[
+                (xcb::CONFIG_WINDOW_X as u16, x as i32 as u32),
+                (xcb::CONFIG_WINDOW_Y as u16, y as i32 as u32),
+            ],
+        )
+        .request_check()?;
+        self.ewmh_connection.flush();
+        Ok(())
+    }
+
+    /// Resize the window, the objects are laid out for the new size with the
+    /// next `draw`
+    pub fn resize(&mut self, width: u16, height: u16) -> anyhow::Result<()> {
+        xcb::configure_window(
+            &self.ewmh_connection,
+            self.window,
+            &[
+                (xcb::CONFIG_WINDOW_WIDTH as u16, width.into()),
+                (xcb::CONFIG_WINDOW_HEIGHT as u16, height.into()),
+            ],
+        )
+        .request_check()?;
+        self.surface
+            .set_size(width.into(), height.into())
+            .map_err(|_| anyhow::anyhow!("Could not resize the surface"))?;
+        self.width = width;
+        self.height = height;
+        self.ewmh_connection.flush();
+        Ok(())
+    }
+
+    /// Put the window above its siblings
+    pub fn raise(&mut self) -> anyhow::Result<()> {
+        xcb::configure_window(
+            &self.ewmh_connection,
+            self.window,
+            &[(xcb::CONFIG_WINDOW_STACK_MODE as u16, xcb::STACK_MODE_ABOVE)],
+        )
+        .request_check()?;
+        self.ewmh_connection.flush();
+        Ok(())
+    }
+
+    pub fn draw(&mut self, mut root_objects: Vec<Object>) -> anyhow::Result<()> {
+        for object in &mut root_objects {
+            object.apply_conditions();
+        }
+        self.stylesheet.apply(&mut root_objects);
 
         let mut stretch = Stretch::new();
 
@@ -143,10 +425,23 @@ impl Window {
 
         fn create_node_objects(
             stretch: &mut Stretch,
+            theme: &Theme,
+            missing_colors: &mut HashSet<String>,
             root: &mut NodeObject,
             children: Vec<Object>,
+            inherited: &Inherited,
         ) -> anyhow::Result<()> {
-            for child in children {
+            for (index, mut child) in children.into_iter().enumerate() {
+                let inherited = child.inherit(inherited);
+
+                for color in child.get_colors() {
+                    if let Color::Var(name) = color {
+                        if theme.get(name).is_none() && missing_colors.insert(name.clone()) {
+                            eprintln!("The color ${} is not defined in the theme", name);
+                        }
+                    }
+                }
+
                 let node = {
                     match child {
                         Object::Container { .. } => stretch
@@ -164,7 +459,28 @@ impl Window {
                     }
                 };
 
+                // A picture that can't be loaded leaves its node empty instead
+                // of keeping everything else from being drawn
+                let picture = child
+                    .get_image_source()
+                    .and_then(|source| source.map(Picture::load).transpose());
+                let picture = match picture {
+                    Ok(picture) => picture,
+                    Err(e) => {
+                        eprintln!("Could not load picture: {}", e);
+                        e.chain().skip(1).for_each(|c| eprintln!("because: {}", c));
+                        None
+                    }
+                };
+
+                let key = match &child.get_attributes().id {
+                    Some(id) => format!("#{}", id),
+                    None => format!("{}/{}", root.key, index),
+                };
+
                 let mut nobj = NodeObject::new(node, Some(child));
+                nobj.picture = picture;
+                nobj.key = key;
 
                 if let Some(ref mut obj) = &mut nobj.object {
                     if let Object::Container {
@@ -172,7 +488,14 @@ impl Window {
                     } = obj
                     {
                         let children = std::mem::replace(children, vec![]);
-                        create_node_objects(stretch, &mut nobj, children)?;
+                        create_node_objects(
+                            stretch,
+                            theme,
+                            missing_colors,
+                            &mut nobj,
+                            children,
+                            &inherited,
+                        )?;
                     }
                 }
                 stretch
@@ -184,7 +507,14 @@ impl Window {
             Ok(())
         }
 
-        create_node_objects(&mut stretch, &mut root_obj, root_objects)?;
+        create_node_objects(
+            &mut stretch,
+            &self.theme,
+            &mut self.missing_colors,
+            &mut root_obj,
+            root_objects,
+            &self.text_defaults,
+        )?;
 
         stretch
             .compute_layout(
@@ -196,139 +526,178 @@ impl Window {
             )
             .map_err(|e| anyhow::anyhow!(e.to_string()))?;
 
-        let root_layout = stretch
-            .layout(root_node)
-            .map_err(|e| anyhow::anyhow!(e.to_string()))?;
+        let previous = self.scene.take().map(|scene| scene.states);
 
-        draw::draw_rectangle(
-            &mut crc,
-            root_layout.location.x,
-            root_layout.location.y,
-            root_layout.size.width,
-            root_layout.size.height,
-            &piet::Color::grey8(0xDD),
-        );
+        self.scene = Some(Scene {
+            stretch,
+            root: root_obj,
+            animations: vec![],
+            marquees: vec![],
+            states: HashMap::new(),
+            animating: false,
+        });
 
-        fn draw_node_objects(
-            stretch: &Stretch,
-            rc: &mut piet_cairo::CairoRenderContext,
-            obj: NodeObject,
-        ) -> anyhow::Result<()> {
-            let node_layout = stretch
-                .layout(obj.node)
-                .map_err(|e| anyhow::anyhow!(e.to_string()))?;
+        self.paint(None, previous.as_ref(), Instant::now())
+    }
 
-            if let Some(obj) = obj.object {
-                match obj {
-                    Object::Container {
-                        background,
-                        corner_radius,
-                        ..
-                    } => {
-                        if let Some(color) = background {
-                            if let Some(radius) = corner_radius {
-                                draw::draw_rounded_rectangle(
-                                    rc,
-                                    node_layout.location.x,
-                                    node_layout.location.y,
-                                    node_layout.size.width,
-                                    node_layout.size.height,
-                                    radius,
-                                    &color,
-                                );
-                            } else {
-                                draw::draw_rectangle(
-                                    rc,
-                                    node_layout.location.x,
-                                    node_layout.location.y,
-                                    node_layout.size.width,
-                                    node_layout.size.height,
-                                    &color,
-                                );
-                            }
-                        }
-                    }
-                    Object::Image { path, .. } => {
-                        let mut file = std::fs::File::open(&path)
-                            .context(format!("Tried opening image file: {}", &path))?;
-                        let surface = cairo::ImageSurface::create_from_png(&mut file)?;
-                        rc.draw_image(
-                            &surface,
-                            kurbo::Rect::from_origin_size(
-                                (node_layout.location.x.into(), node_layout.location.y.into()),
-                                (
-                                    node_layout.size.width.into(),
-                                    node_layout.size.height.into(),
-                                ),
-                            ),
-                            piet::InterpolationMode::Bilinear,
-                        );
+    /// Whether transitions or keyframe animations need every frame repainted
+    fn is_animating(&self) -> bool {
+        !self.transitions.is_empty() || self.scene.as_ref().map_or(false, |scene| scene.animating)
+    }
+
+    /// Advance transitions, keyframe animations, animated pictures and marquees
+    ///
+    /// Running transitions and keyframe animations repaint everything,
+    /// otherwise only the pictures that changed their frame and the texts that
+    /// scrolled are repainted.
+    pub fn tick(&mut self) -> anyhow::Result<()> {
+        let now = Instant::now();
+
+        if self.is_animating() {
+            self.paint(None, None, now)?;
+            self.transitions
+                .retain(|_, transition| !transition.is_finished(now));
+            return Ok(());
+        }
+
+        let damage: Vec<kurbo::Rect> = match &mut self.scene {
+            Some(scene) => scene
+                .animations
+                .iter_mut()
+                .filter_map(|animation| {
+                    let frame = animation.picture.frame_index(now);
+                    if frame == animation.frame {
+                        return None;
                     }
-                    Object::Text {
-                        text,
-                        font,
-                        font_size,
-                        color,
-                        ..
-                    } => {
-                        use piet::{FontBuilder, Text, TextLayoutBuilder};
-
-                        let text_builder = rc.text();
-                        let font = text_builder
-                            .new_font_by_name(&font, font_size)
-                            .build()
-                            .map_err(|e| anyhow::anyhow!(e.to_string()))?;
-                        let text_layout = text_builder
-                            .new_text_layout(&font, &text)
-                            .build()
-                            .map_err(|e| anyhow::anyhow!(e.to_string()))?;
-                        let brush = rc.solid_brush(color);
-                        // draw::draw_rectangle(
-                        //     rc,
-                        //     node_layout.location.x,
-                        //     node_layout.location.y,
-                        //     node_layout.size.width,
-                        //     node_layout.size.height,
-                        //     &piet::Color::rgba(0xDD, 0xDD, 0xDD, 100),
-                        // );
-                        let half_height = node_layout.size.height;
-                        rc.draw_text(
-                            &text_layout,
-                            (
-                                node_layout.location.x as f64,
-                                (node_layout.location.y + half_height) as f64,
-                            ),
-                            &brush,
-                        );
+                    animation.frame = frame;
+                    Some(animation.area)
+                })
+                .chain(scene.marquees.iter_mut().filter_map(|text| {
+                    let offset = text.offset_at(now);
+                    if offset == text.offset {
+                        return None;
                     }
-                }
-            }
-            rc.save().map_err(|e| anyhow::anyhow!(e.to_string()))?;
-            rc.transform(kurbo::Affine::translate((
-                node_layout.location.x as f64,
-                node_layout.location.y as f64,
-            )));
-            rc.clip(kurbo::Rect::new(
-                0.,
-                0.,
-                node_layout.size.width as f64,
-                node_layout.size.height as f64,
-            ));
-            for child in obj.children {
-                draw_node_objects(stretch, rc, child)?;
+                    text.offset = offset;
+                    Some(text.area)
+                }))
+                .collect(),
+            None => return Ok(()),
+        };
+
+        if damage.is_empty() {
+            return Ok(());
+        }
+
+        self.paint(Some(&damage[..]), None, now)
+    }
+
+    /// When `tick` should be called next, if anything is animated
+    pub fn next_frame(&self) -> Option<Instant> {
+        let now = Instant::now();
+
+        if self.is_animating() {
+            return Some(now + animation::FRAME_INTERVAL);
+        }
+
+        let scene = self.scene.as_ref()?;
+        scene
+            .animations
+            .iter()
+            .filter_map(|animation| animation.picture.next_frame(now))
+            .chain(
+                scene
+                    .marquees
+                    .iter()
+                    .filter_map(|text| text.next_frame(now)),
+            )
+            .min()
+    }
+
+    /// Paint the current scene, either completely or only where damaged
+    fn paint(
+        &mut self,
+        damage: Option<&[kurbo::Rect]>,
+        previous: Option<&HashMap<String, NodeState>>,
+        now: Instant,
+    ) -> anyhow::Result<()> {
+        let scene = match &mut self.scene {
+            Some(scene) => scene,
+            None => return Ok(()),
+        };
+
+        self.context.save();
+        if let Some(damage) = damage {
+            for area in damage {
+                self.context
+                    .rectangle(area.x0, area.y0, area.width(), area.height());
             }
-            rc.restore().map_err(|e| anyhow::anyhow!(e.to_string()))?;
-            Ok(())
+            self.context.clip();
         }
 
-        draw_node_objects(&stretch, &mut crc, root_obj)?;
+        self.context.push_group();
 
-        crc.finish().map_err(|e| anyhow::anyhow!(e.to_string()))?;
+        let mut painter = Painter {
+            now,
+            cairo: self.context.clone(),
+            damage,
+            animations: vec![],
+            marquees: vec![],
+            previous,
+            transitions: &mut self.transitions,
+            states: HashMap::new(),
+            animation_starts: &mut self.animation_starts,
+            animated: HashSet::new(),
+            animating: false,
+            theme: &self.theme,
+        };
+
+        {
+            let mut crc = piet_cairo::CairoRenderContext::new(&mut self.context);
+            crc.clear(piet::Color::WHITE);
+
+            let root_layout = scene
+                .stretch
+                .layout(scene.root.node)
+                .map_err(|e| anyhow::anyhow!(e.to_string()))?;
+
+            draw::draw_rectangle(
+                &mut crc,
+                root_layout.location.x,
+                root_layout.location.y,
+                root_layout.size.width,
+                root_layout.size.height,
+                &piet::Color::grey8(0xDD),
+            );
+
+            draw_node_objects(
+                &scene.stretch,
+                &mut crc,
+                &scene.root,
+                (0., 0.),
+                &mut painter,
+            )?;
+
+            crc.finish().map_err(|e| anyhow::anyhow!(e.to_string()))?;
+        }
 
         self.context.pop_group_to_source();
         self.context.paint();
+        self.context.restore();
 
-        xcb::map_window(&self.ewmh_connection, self.window).request_check()?;
+        if damage.is_none() {
+            scene.animations = painter.animations;
+            scene.marquees = painter.marquees;
+            scene.states = painter.states;
+            scene.animating = painter.animating;
+
+            let animated = painter.animated;
+            self.animation_starts
+                .retain(|key, _| animated.contains(key));
+        }
+
+        if !self.hidden {
+            xcb::map_window(&self.ewmh_connection, self.window).request_check()?;
+        }
         self.ewmh_connection.flush();
         Ok(())
     }
@@ -340,6 +709,316 @@ impl Window {
     }
 }
 
+fn draw_node_objects(
+    stretch: &Stretch,
+    rc: &mut piet_cairo::CairoRenderContext,
+    obj: &NodeObject,
+    offset: (f64, f64),
+    painter: &mut Painter,
+) -> anyhow::Result<()> {
+    let node_layout = stretch
+        .layout(obj.node)
+        .map_err(|e| anyhow::anyhow!(e.to_string()))?;
+
+    let target = NodeState {
+        area: kurbo::Rect::from_origin_size(
+            (
+                offset.0 + node_layout.location.x as f64,
+                offset.1 + node_layout.location.y as f64,
+            ),
+            (
+                node_layout.size.width as f64,
+                node_layout.size.height as f64,
+            ),
+        ),
+        background: obj
+            .object
+            .as_ref()
+            .and_then(Object::get_background)
+            .and_then(|color| painter.theme.resolve(color)),
+        color: obj
+            .object
+            .as_ref()
+            .and_then(Object::get_color)
+            .and_then(|color| painter.theme.resolve(color)),
+        opacity: obj
+            .object
+            .as_ref()
+            .and_then(|object| object.get_attributes().opacity)
+            .unwrap_or(1.),
+    };
+
+    let attributes = obj.object.as_ref().map(Object::get_attributes);
+    let id = attributes.and_then(|attributes| attributes.id.as_ref());
+
+    if let (Some(id), Some(previous)) = (id, painter.previous) {
+        let transition = attributes.and_then(|attributes| attributes.transition.as_ref());
+        let retargeted = painter
+            .transitions
+            .get(id)
+            .map_or(true, |active| !active.to.same_as(&target));
+
+        match (transition, previous.get(id)) {
+            (Some(transition), Some(from)) if retargeted && !from.same_as(&target) => {
+                painter.transitions.insert(
+                    id.clone(),
+                    ActiveTransition {
+                        from: from.clone(),
+                        to: target.clone(),
+                        start: painter.now,
+                        transition: transition.clone(),
+                    },
+                );
+            }
+            (Some(_), Some(_)) => {}
+            _ => {
+                painter.transitions.remove(id);
+            }
+        }
+    }
+
+    let mut state = match id.and_then(|id| painter.transitions.get(id)) {
+        Some(active) => active.state_at(&target, painter.now),
+        None => target,
+    };
+
+    if let Some(id) = id {
+        painter.states.insert(id.clone(), state.clone());
+    }
+
+    // Moves and scales the object and its children around its center
+    let mut translate = kurbo::Vec2::new(0., 0.);
+    let mut scale = 1.;
+
+    if let Some(keyframes) = attributes.and_then(|attributes| attributes.animation.as_ref()) {
+        let start = *painter
+            .animation_starts
+            .entry(obj.key.clone())
+            .or_insert(painter.now);
+        painter.animated.insert(obj.key.clone());
+
+        match keyframes.progress(painter.now.saturating_duration_since(start)) {
+            Progress::Waiting => painter.animating = true,
+            Progress::Running(progress) => {
+                painter.animating = true;
+                state.opacity = keyframes.value(progress, state.opacity, |k| k.opacity);
+                let theme = painter.theme;
+                state.background = keyframes.color(progress, state.background.as_ref(), |k| {
+                    k.background.as_ref().and_then(|color| theme.resolve(color))
+                });
+                state.color = keyframes.color(progress, state.color.as_ref(), |k| {
+                    k.color.as_ref().and_then(|color| theme.resolve(color))
+                });
+                translate = kurbo::Vec2::new(
+                    keyframes.value(progress, 0., |k| k.translate.map(|t| t.x)),
+                    keyframes.value(progress, 0., |k| k.translate.map(|t| t.y)),
+                );
+                scale = keyframes.value(progress, 1., |k| k.scale);
+            }
+            Progress::Finished => {}
+        }
+    }
+
+    let transformed = translate != kurbo::Vec2::new(0., 0.) || scale != 1.;
+    if let Some(damage) = painter.damage {
+        let area = transform_area(&state.area, translate, scale);
+        if !damage.iter().any(|damaged| overlaps(damaged, &area)) {
+            return Ok(());
+        }
+    }
+
+    // Everything is drawn relative to the parent
+    let bounds = state.area - kurbo::Vec2::new(offset.0, offset.1);
+
+    let translucent = state.opacity < 1.;
+    if translucent {
+        painter.cairo.push_group();
+    }
+
+    if transformed {
+        let center = kurbo::Vec2::new(
+            bounds.x0 + bounds.width() / 2.,
+            bounds.y0 + bounds.height() / 2.,
+        );
+        rc.save().map_err(|e| anyhow::anyhow!(e.to_string()))?;
+        rc.transform(
+            kurbo::Affine::translate(center + translate)
+                * kurbo::Affine::scale(scale)
+                * kurbo::Affine::translate(-center),
+        );
+    }
+
+    if let Some(object) = &obj.object {
+        match object {
+            Object::Container { corner_radius, .. } => {
+                if let Some(color) = &state.background {
+                    if let Some(radius) = corner_radius {
+                        draw::draw_rounded_rectangle(
+                            rc,
+                            bounds.x0 as f32,
+                            bounds.y0 as f32,
+                            bounds.width() as f32,
+                            bounds.height() as f32,
+                            *radius,
+                            color,
+                        );
+                    } else {
+                        draw::draw_rectangle(
+                            rc,
+                            bounds.x0 as f32,
+                            bounds.y0 as f32,
+                            bounds.width() as f32,
+                            bounds.height() as f32,
+                            color,
+                        );
+                    }
+                }
+            }
+            // Left empty if the picture could not be loaded
+            Object::Image { .. } if obj.picture.is_none() => {}
+            Object::Image {
+                object_fit,
+                object_position,
+                interpolation,
+                ..
+            } => {
+                let picture = obj.picture.as_ref().context("Image was not loaded")?;
+                let dest = object_fit.place(*object_position, picture.size(), bounds);
+                let mut surface = picture.to_surface(dest.width(), dest.height(), painter.now)?;
+                if let Some(color) = &state.color {
+                    surface = crate::picture::tint(&surface, color)?;
+                }
+
+                rc.save().map_err(|e| anyhow::anyhow!(e.to_string()))?;
+                rc.clip(bounds);
+                rc.draw_image(&surface, dest, (*interpolation).into());
+                rc.restore().map_err(|e| anyhow::anyhow!(e.to_string()))?;
+
+                if picture.next_frame(painter.now).is_some() {
+                    painter.animations.push(AnimatedPicture {
+                        picture: picture.clone(),
+                        area: state.area,
+                        frame: picture.frame_index(painter.now),
+                    });
+                }
+            }
+            Object::Text {
+                text,
+                font,
+                font_size,
+                overflow,
+                marquee,
+                ..
+            } => {
+                use piet::{FontBuilder, Text, TextLayout, TextLayoutBuilder};
+
+                let text_builder = rc.text();
+                let font = text_builder
+                    .new_font_by_name(
+                        font.as_deref().unwrap_or(object::DEFAULT_FONT),
+                        font_size.unwrap_or(object::DEFAULT_FONT_SIZE),
+                    )
+                    .build()
+                    .map_err(|e| anyhow::anyhow!(e.to_string()))?;
+                let text_layout = text_builder
+                    .new_text_layout(&font, text)
+                    .build()
+                    .map_err(|e| anyhow::anyhow!(e.to_string()))?;
+                // Colors missing from the theme were warned about in `draw`
+                let color = state.color.clone().unwrap_or(piet::Color::BLACK);
+                let brush = rc.solid_brush(color);
+                // draw::draw_rectangle(
+                //     rc,
+                //     node_layout.location.x,
+                //     node_layout.location.y,
+                //     node_layout.size.width,
+                //     node_layout.size.height,
+                //     &piet::Color::rgba(0xDD, 0xDD, 0xDD, 100),
+                // );
+                let half_height = bounds.height();
+                let text_width = text_layout.width();
+
+                let mut scrolled = 0.;
+                let marquee = marquee.unwrap_or_default();
+                let scrolling = *overflow == Overflow::Marquee && text_width > bounds.width();
+                if scrolling {
+                    // Restart the marquee whenever the text changes
+                    let key = format!("{}:marquee:{}", obj.key, text);
+                    let start = *painter
+                        .animation_starts
+                        .entry(key.clone())
+                        .or_insert(painter.now);
+                    painter.animated.insert(key);
+
+                    let text = ScrollingText {
+                        marquee,
+                        start,
+                        text_width,
+                        box_width: bounds.width(),
+                        area: state.area,
+                        offset: 0.,
+                    };
+                    scrolled = text.offset_at(painter.now);
+                    painter.marquees.push(ScrollingText {
+                        offset: scrolled,
+                        ..text
+                    });
+                }
+
+                if *overflow != Overflow::Visible {
+                    rc.save().map_err(|e| anyhow::anyhow!(e.to_string()))?;
+                    rc.clip(bounds);
+                }
+
+                rc.draw_text(
+                    &text_layout,
+                    (bounds.x0 - scrolled, bounds.y0 + half_height),
+                    &brush,
+                );
+                if scrolling && marquee.mode == MarqueeMode::Loop {
+                    rc.draw_text(
+                        &text_layout,
+                        (
+                            bounds.x0 - scrolled + text_width + marquee.gap,
+                            bounds.y0 + half_height,
+                        ),
+                        &brush,
+                    );
+                }
+
+                if *overflow != Overflow::Visible {
+                    rc.restore().map_err(|e| anyhow::anyhow!(e.to_string()))?;
+                }
+            }
+        }
+    }
+    rc.save().map_err(|e| anyhow::anyhow!(e.to_string()))?;
+    rc.transform(kurbo::Affine::translate((bounds.x0, bounds.y0)));
+    rc.clip(kurbo::Rect::new(0., 0., bounds.width(), bounds.height()));
+    // The areas of the children don't say where a transform moves them, so
+    // they are all drawn
+    let damage = painter.damage;
+    if transformed {
+        painter.damage = None;
+    }
+    for child in &obj.children {
+        draw_node_objects(stretch, rc, child, (state.area.x0, state.area.y0), painter)?;
+    }
+    painter.damage = damage;
+    rc.restore().map_err(|e| anyhow::anyhow!(e.to_string()))?;
+
+    if transformed {
+        rc.restore().map_err(|e| anyhow::anyhow!(e.to_string()))?;
+    }
+
+    if translucent {
+        painter.cairo.pop_group_to_source();
+        painter.cairo.paint_with_alpha(state.opacity.max(0.));
+    }
+
+    Ok(())
+}
+
 pub enum WindowEvent {
     Draw,
     Unknown,